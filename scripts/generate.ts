@@ -1,4 +1,4 @@
-import { readdir, writeFile } from "node:fs/promises";
+import { readdir, readFile } from "node:fs/promises";
 import { join } from "node:path";
 import pptxgen from "pptxgenjs";
 
@@ -6,8 +6,16 @@ const presentation = new pptxgen();
 const dataDir = join(import.meta.dirname, "..", "data");
 const slidesDir = join(dataDir, "slides");
 const presentationPath = join(dataDir, "presentation.pptx");
+const notesPath = join(dataDir, "notes.json");
+
+// city summaries by slide name, missing when no summaries were scraped
+const notes: Record<string, string> = await readFile(notesPath, "utf8")
+    .then((text) => JSON.parse(text))
+    .catch(() => ({}));
 
 const slides = await readdir(slidesDir);
+// webp or png, whichever export feature the slides were made with
+const titleFile = slides.find((file) => /^title\.[^.]+$/.test(file));
 
 const sortedSlides = slides
     .map((file) => {
@@ -19,7 +27,7 @@ const sortedSlides = slides
             filename: file,
         };
     })
-    .filter(x => x.filename !== "title.webp")
+    .filter(x => x.filename !== titleFile)
     .sort((a, b) => {
         if (a.index !== b.index) return a.index - b.index;
         return a.slide_index - b.slide_index;
@@ -29,21 +37,27 @@ console.log(sortedSlides)
 
 // add title slide
 
-const titleSlide = presentation.addSlide();
-titleSlide.addImage({
-    path: join(slidesDir, "title.webp"),
-    w: "100%",
-    h: "100%",
-});
+if (titleFile) {
+    const titleSlide = presentation.addSlide();
+    titleSlide.addImage({
+        path: join(slidesDir, titleFile),
+        w: "100%",
+        h: "100%",
+    });
+}
 
 // add the rest
 
 sortedSlides.forEach(({ filename }) => {
-    presentation.addSlide().addImage({
+    const slide = presentation.addSlide();
+    slide.addImage({
         path: join(slidesDir, filename),
         w: "100%",
         h: "100%",
     });
+
+    const slideNotes = notes[filename.replace(/\.[^.]+$/, "")];
+    if (slideNotes) slide.addNotes(slideNotes);
 });
 
 // write & output
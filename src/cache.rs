@@ -0,0 +1,96 @@
+use crate::{
+    utils::{AppResult, Context},
+    validate::write_atomic,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A response body with the validators needed to ask the server whether it changed
+#[derive(Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: String,
+}
+
+/// Response cache keyed by URL, one JSON file per entry
+pub struct HttpCache {
+    dir: Option<PathBuf>,
+    /// Serve only from the cache and never touch the network
+    pub offline: bool,
+}
+
+/// FNV-1a, stable across Rust releases unlike the std hasher, so the file names stay valid
+fn url_hash(url: &str) -> u64 {
+    url.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl HttpCache {
+    pub fn new(dir: impl Into<PathBuf>, offline: bool) -> Self {
+        Self {
+            dir: Some(dir.into()),
+            offline,
+        }
+    }
+
+    /// A cache that never stores anything
+    pub fn disabled() -> Self {
+        Self {
+            dir: None,
+            offline: false,
+        }
+    }
+
+    fn entry_path(dir: &Path, url: &str) -> PathBuf {
+        dir.join(format!("{:016x}.json", url_hash(url)))
+    }
+
+    pub fn load(&self, url: &str) -> AppResult<Option<CacheEntry>> {
+        let Some(dir) = &self.dir else {
+            return Ok(None);
+        };
+
+        let path = Self::entry_path(dir, url);
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).path(&path),
+        };
+
+        // an entry that won't parse, damaged or from an older version, is fetched again
+        let Ok(entry) = serde_json::from_str::<CacheEntry>(&data) else {
+            return Ok(None);
+        };
+        // a hash collision is next to impossible, but a wrong page would be hard to track down
+        Ok((entry.url == url).then_some(entry))
+    }
+
+    pub fn remove(&self, url: &str) -> AppResult<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+
+        let path = Self::entry_path(dir, url);
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err).path(&path),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes through a temporary file, an interrupted run never leaves a torn entry behind
+    pub fn store(&self, entry: &CacheEntry) -> AppResult<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+
+        fs::create_dir_all(dir).path(dir)?;
+        let path = Self::entry_path(dir, &entry.url);
+        write_atomic(&path, serde_json::to_string(entry)?.as_bytes())
+    }
+}
@@ -0,0 +1,115 @@
+use crate::wiki::FileInfo;
+
+/// Narrowest photo worth considering, anything smaller is an icon or a logo
+const MIN_WIDTH: u32 = 400;
+
+/// Words in file names of maps, symbols and other images that aren't photos of the city,
+/// see [`has_word`]
+const NOT_PHOTOS: [&str; 11] = [
+    "map",
+    "locator",
+    "location",
+    "lokalizacja",
+    "herb",
+    "coa",
+    "coat of arms",
+    "wappen",
+    "flag",
+    "logo",
+    "icon",
+];
+
+/// Words in file names of coats of arms, for sources that don't say which file is one
+const COA_WORDS: [&str; 4] = ["herb", "coa", "coat of arms", "wappen"];
+
+/// Whether one of `words` is in the file `name` as a whole word, or words in a row for phrases
+/// like `coat of arms`. `coa` isn't in `Coast` and `herb` isn't in `Herby`
+fn has_word(name: &str, words: &[&str]) -> bool {
+    let split = |text: &str| -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    };
+
+    let name = split(name);
+    words.iter().any(|phrase| {
+        let phrase = split(phrase);
+        name.windows(phrase.len()).any(|window| window == phrase)
+    })
+}
+
+/// Whether two file names point at the same file, the API uses spaces where links use underscores
+pub fn same_file(a: &str, b: &str) -> bool {
+    let normalize = |name: &str| name.replace('_', " ").to_lowercase();
+    normalize(a) == normalize(b)
+}
+
+/// The file among `files` that looks like a coat of arms, vector ones first
+pub fn coat_of_arms(files: &[FileInfo]) -> Option<&str> {
+    let found: Vec<_> = files
+        .iter()
+        .filter(|file| has_word(&file.file, &COA_WORDS))
+        .collect();
+    let best = found
+        .iter()
+        .find(|file| file.mime.contains("svg"))
+        .or(found.first())?;
+    Some(&best.file)
+}
+
+/// How good a background `file` would make, `None` when it can't be one at all.
+/// `preferred` is the photo Wikidata or the infobox picks, `coa` the coat of arms
+pub fn score(file: &FileInfo, preferred: Option<&str>, coa: Option<&str>) -> Option<i32> {
+    if file.mime.contains("svg")
+        || file.width < MIN_WIDTH
+        || has_word(&file.file, &NOT_PHOTOS)
+        || coa.is_some_and(|coa| same_file(coa, &file.file))
+    {
+        return None;
+    }
+
+    let mut score = 0;
+
+    // the entry is a 1920x270 strip, a wide photo loses the least when cropped to it
+    let aspect_ratio = file.width as f32 / file.height.max(1) as f32;
+    score += match aspect_ratio {
+        ..1.0 => -3,
+        1.0..1.3 => 0,
+        _ => 3,
+    };
+
+    score += match file.width {
+        1920.. => 2,
+        1280..1920 => 1,
+        800..1280 => 0,
+        _ => -2,
+    };
+
+    if file.mime == "image/jpeg" {
+        score += 1;
+    }
+
+    if preferred.is_some_and(|preferred| same_file(preferred, &file.file)) {
+        score += 2;
+    }
+
+    Some(score)
+}
+
+/// Usable `files` with their scores, the best first, ties keep the order of the article
+pub fn rank(
+    files: Vec<FileInfo>,
+    preferred: Option<&str>,
+    coa: Option<&str>,
+) -> Vec<(FileInfo, i32)> {
+    let mut ranked: Vec<_> = files
+        .into_iter()
+        .filter_map(|file| {
+            let score = score(&file, preferred, coa)?;
+            Some((file, score))
+        })
+        .collect();
+    ranked.sort_by_key(|(_, score)| -score);
+    ranked
+}
@@ -0,0 +1,106 @@
+use clap::{ArgAction, Parser, Subcommand};
+use geo_pres::{
+    SuffixStrategy,
+    config::error_rate,
+    logger::{LogFormat, LogLevel, LoggerConfig},
+};
+use std::path::PathBuf;
+
+fn max_error_rate(value: &str) -> Result<f64, String> {
+    let rate = value.parse().map_err(|err| format!("{err}"))?;
+    error_rate(rate)
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Generates slides presenting every Polish city")]
+pub struct Args {
+    /// What to do, runs the whole pipeline when omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Config file to use instead of data/config.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Where to write the JSON run report instead of data/report.json
+    #[arg(long, global = true, value_name = "PATH")]
+    pub report: Option<PathBuf>,
+
+    /// Where to keep failed items instead of data/failures.json
+    #[arg(long, global = true, value_name = "PATH")]
+    pub failures: Option<PathBuf>,
+
+    /// Manual article and image overrides to use instead of data/overrides.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    pub overrides: Option<PathBuf>,
+
+    /// Highest share of failed items (0.0 - 1.0) before exiting with a non-zero code,
+    /// overrides `report.max_error_rate` from the config
+    #[arg(long, global = true, value_name = "RATE", value_parser = max_error_rate)]
+    pub max_error_rate: Option<f64>,
+
+    /// Answer wiki API requests from data/cache/http only and skip downloads,
+    /// overrides `http.offline` from the config
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Show more details, can be repeated (-vv)
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Show only warnings, or only errors when repeated (-qq)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub quiet: u8,
+
+    /// Also append log lines to this file, without colours
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Format of log lines
+    #[arg(long, global = true, value_enum, default_value_t)]
+    pub log_format: LogFormat,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the whole pipeline, the default
+    Run,
+    /// Run the pipeline again for the items listed in the failures file only
+    Retry(RetryArgs),
+    /// Download the images whose links are in data/manifest.json without scraping again
+    Download,
+    /// Re-render slides whenever fonts, icons, maps, source images, the dataset, the facts
+    /// or the config change
+    Watch,
+    /// Report coats of arms and backgrounds that several cities share to data/duplicates.json
+    Duplicates(DuplicatesArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RetryArgs {
+    /// Order in which article title suffixes are tried
+    #[arg(long, value_enum, default_value_t)]
+    pub suffixes: SuffixStrategy,
+
+    /// Additional article title suffix to try, e.g. "_(gmina_{powiat})", can be repeated
+    #[arg(long = "extra-suffix", value_name = "SUFFIX")]
+    pub extra_suffixes: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DuplicatesArgs {
+    /// How many of the 64 bits of the perceptual hashes of two images can differ
+    /// for them to count as the same, 5 when omitted
+    #[arg(long, value_name = "BITS")]
+    pub max_distance: Option<u32>,
+}
+
+impl Args {
+    pub fn logger_config(&self) -> LoggerConfig {
+        LoggerConfig {
+            level: LogLevel::from_verbosity(self.verbose, self.quiet),
+            format: self.log_format,
+            file: self.log_file.clone(),
+        }
+    }
+}
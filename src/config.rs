@@ -0,0 +1,366 @@
+//! Settings read from `data/config.toml`, every section and field has a default.
+
+use crate::{
+    facts::FactKind,
+    log,
+    logger::LogStyle,
+    utils::{AppResult, Reason},
+};
+use serde::Deserialize;
+use std::{path::Path, time::Duration};
+
+/// Everything `data/config.toml` can set, see [`Config::load`]
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// `[report]`
+    pub report: ReportConfig,
+    /// `[scraper]`
+    pub scraper: ScraperConfig,
+    /// `[http]`
+    pub http: HttpConfig,
+    /// `[concurrency]`
+    pub concurrency: ConcurrencyConfig,
+    /// `[slides]`
+    pub slides: SlidesConfig,
+}
+
+/// When a run counts as failed, see [`crate::report::RunReport`]
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportConfig {
+    /// Highest share of failed items (0.0 - 1.0) that still counts as a successful run
+    pub max_error_rate: f64,
+}
+
+/// `rate` when it's a share between 0.0 and 1.0, what `--max-error-rate` is checked with too
+pub fn error_rate(rate: f64) -> Result<f64, String> {
+    if (0.0..=1.0).contains(&rate) {
+        Ok(rate)
+    } else {
+        Err(format!("{rate:?} isn't between 0.0 and 1.0"))
+    }
+}
+
+impl ReportConfig {
+    /// Rejects a rate that would fail or pass every run whatever happened in it
+    pub fn validate(&self) -> AppResult<()> {
+        error_rate(self.max_error_rate).map_err(|problem| {
+            Reason::InvalidConfig(format!("report.max_error_rate: {problem}"))
+        })?;
+        Ok(())
+    }
+}
+
+/// Somewhere the scraper looks for a city's images, see [`ScraperConfig::sources`]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Source {
+    /// A Wikipedia, the article comes from the Wikidata sitelink to `site`
+    Wikipedia {
+        /// MediaWiki action API endpoint, point it at a local server to test without Wikipedia
+        api_url: String,
+        /// Name of the wiki in Wikidata sitelinks, e.g. `plwiki`
+        site: String,
+        /// Also guess titles from the city name and check them against the infobox,
+        /// the checks read Polish infoboxes
+        #[serde(default)]
+        guess_titles: bool,
+        /// Infobox parameters holding the coat of arms, the first one set wins
+        #[serde(default)]
+        coa_params: Vec<String>,
+        /// Infobox parameters holding a photo of the city, the first one set wins
+        #[serde(default)]
+        photo_params: Vec<String>,
+        /// Infobox parameters facts about the city are read from
+        #[serde(default)]
+        fact_params: Box<FactParams>,
+    },
+    /// The Commons category of the city, named in its Wikidata item
+    Commons {
+        /// MediaWiki action API endpoint of Commons
+        api_url: String,
+    },
+}
+
+impl Source {
+    /// How the source is recorded next to the images it gave, the wiki or `commons`
+    pub fn name(&self) -> &str {
+        match self {
+            Source::Wikipedia { site, .. } => site,
+            Source::Commons { .. } => "commons",
+        }
+    }
+
+    fn wikipedia(
+        language: &str,
+        guess_titles: bool,
+        coa: &[&str],
+        photo: &[&str],
+        fact_params: FactParams,
+    ) -> Self {
+        Source::Wikipedia {
+            api_url: format!("https://{language}.wikipedia.org/w/api.php"),
+            site: format!("{language}wiki"),
+            guess_titles,
+            coa_params: params(coa),
+            photo_params: params(photo),
+            fact_params: Box::new(fact_params),
+        }
+    }
+}
+
+fn params(params: &[&str]) -> Vec<String> {
+    params.iter().map(|&param| param.into()).collect()
+}
+
+/// Infobox parameters holding each fact, the first one that can be read wins.
+/// See [`crate::facts::Facts`] for what's made of them.
+///
+/// Facts of a city are read together with its images. Cities scraped before facts were read
+/// have no entry in `data/facts.json`, so the first run after upgrading looks every one of them
+/// up again, about 1000 articles, before the runs go back to only the cities that need it
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FactParams {
+    /// Year the town got its rights
+    pub town_rights: Vec<String>,
+    /// Metres above sea level
+    pub elevation: Vec<String>,
+    /// Postal code, the first of a range is kept
+    pub postal_code: Vec<String>,
+    /// Telephone area code
+    pub area_code: Vec<String>,
+    /// Mayor or whoever else runs the city
+    pub mayor: Vec<String>,
+    /// Parameters with a `{{coord}}` template, the `stopni N`, `minut N`, ... parameters
+    /// Polish infoboxes split coordinates into are read without being listed
+    pub coordinates: Vec<String>,
+}
+
+/// How the articles and images of the cities are found
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScraperConfig {
+    /// Where images are looked for, in order, the next one is only asked about the cities
+    /// the ones before it had nothing usable for
+    pub sources: Vec<Source>,
+    /// Wikidata API endpoint, items are looked up by TERYT before any article title is guessed
+    pub wikidata_url: String,
+    /// Lowest verification score a guessed article needs, every matching voivodeship, powiat
+    /// and population is worth 1 point, a mismatch takes 1 away and a matching TERYT gives 10
+    pub min_score: i32,
+    /// Licences an image may be under, compared without the version (`CC BY-SA` covers
+    /// `CC BY-SA 3.0 pl`), an empty list allows any
+    pub licences: Vec<String>,
+    /// Runner-up backgrounds downloaded next to the best one, the image editor falls back
+    /// to them in order when the best one can't be edited
+    pub spare_backgrounds: usize,
+    /// Longest summary made of the lead of a city's article, in characters, cut at the end of
+    /// a sentence. 0 skips fetching them, summaries already scraped keep their length.
+    /// Turning them on looks up every city without a summary again on the next run
+    pub summary_chars: usize,
+    /// Deprecated, the endpoint of the `plwiki` source from before there were sources.
+    /// Set `api_url` of that source in `sources` instead
+    pub api_url: Option<String>,
+}
+
+impl ScraperConfig {
+    /// Moves the deprecated `api_url` over to the `plwiki` source
+    fn migrate(&mut self) -> AppResult<()> {
+        let Some(url) = self.api_url.take() else {
+            return Ok(());
+        };
+
+        let plwiki = self.sources.iter_mut().find_map(|source| match source {
+            Source::Wikipedia { api_url, site, .. } if site == "plwiki" => Some(api_url),
+            _ => None,
+        });
+        let Some(api_url) = plwiki else {
+            return Err(Reason::InvalidConfig(
+                "scraper.api_url is deprecated and there's no plwiki source to apply it to, \
+                 set api_url of a [[scraper.sources]] entry instead"
+                    .into(),
+            )
+            .into());
+        };
+
+        log!(
+            Warn: [LogStyle::Yellow],
+            "CONFIG",
+            "scraper.api_url is deprecated, set api_url of the plwiki source in [[scraper.sources]] instead"
+        );
+        *api_url = url;
+        Ok(())
+    }
+}
+
+impl Default for ScraperConfig {
+    fn default() -> Self {
+        Self {
+            sources: vec![
+                Source::wikipedia(
+                    "pl",
+                    true,
+                    &["herb"],
+                    &["zdjęcie"],
+                    FactParams {
+                        town_rights: params(&["prawa miejskie"]),
+                        elevation: params(&["wysokość"]),
+                        postal_code: params(&["kod pocztowy"]),
+                        area_code: params(&["strefa numeracyjna"]),
+                        mayor: params(&["zarządzający"]),
+                        coordinates: vec![],
+                    },
+                ),
+                Source::wikipedia(
+                    "en",
+                    false,
+                    &["image_shield"],
+                    &["image_skyline"],
+                    FactParams {
+                        elevation: params(&["elevation_m"]),
+                        postal_code: params(&["postal_code"]),
+                        area_code: params(&["area_code"]),
+                        mayor: params(&["leader_name"]),
+                        coordinates: params(&["coordinates"]),
+                        ..Default::default()
+                    },
+                ),
+                Source::wikipedia(
+                    "de",
+                    false,
+                    &["Wappen"],
+                    &["Bild"],
+                    FactParams {
+                        elevation: params(&["Höhe"]),
+                        postal_code: params(&["PLZ"]),
+                        area_code: params(&["Vorwahl"]),
+                        ..Default::default()
+                    },
+                ),
+                Source::Commons {
+                    api_url: "https://commons.wikimedia.org/w/api.php".into(),
+                },
+            ],
+            wikidata_url: "https://www.wikidata.org/w/api.php".into(),
+            min_score: 2,
+            licences: ["CC0", "Public domain", "CC BY", "CC BY-SA"]
+                .map(String::from)
+                .to_vec(),
+            spare_backgrounds: 2,
+            summary_chars: 0,
+            api_url: None,
+        }
+    }
+}
+
+/// How every request to the wikis and for images is made
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Put into the User-Agent, Wikimedia blocks clients it can't contact
+    pub contact: String,
+    /// Time a single request may take, connecting included
+    pub timeout_secs: f64,
+    /// How many times a timed out, rate limited or 5xx request is repeated
+    pub retries: u32,
+    /// Delay before the first retry, doubled with every next one
+    pub backoff_ms: u64,
+    /// Longest delay between retries
+    pub max_backoff_secs: f64,
+    /// 0 disables the limit
+    pub requests_per_second: f64,
+    /// Keep API responses in data/cache/http and revalidate them instead of downloading them again
+    pub cache: bool,
+    /// Answer API requests from the cache only and skip downloads, `--offline` turns it on too
+    pub offline: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            contact: "https://github.com/Nimplex/geo-pres".into(),
+            timeout_secs: 30.0,
+            retries: 4,
+            backoff_ms: 500,
+            max_backoff_secs: 30.0,
+            requests_per_second: 10.0,
+            cache: true,
+            offline: false,
+        }
+    }
+}
+
+/// What goes on the slides
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlidesConfig {
+    /// Put a small "fot. author / licence" caption on every city entry
+    pub captions: bool,
+    /// Facts from the infobox shown under the powiat on every city entry, in this order,
+    /// e.g. `["town_rights", "elevation"]`
+    pub facts: Vec<FactKind>,
+}
+
+/// How many items each stage works on at once
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    /// Cities looked up at once
+    pub scraper: usize,
+    /// Cities whose images are downloaded at once
+    pub downloader: usize,
+    /// Images edited at once
+    pub image_editor: usize,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            scraper: 10,
+            downloader: 10,
+            image_editor: 32,
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Rejects durations the client can't be built with, negative, NaN or too long
+    pub fn validate(&self) -> AppResult<()> {
+        let seconds = |field: &str, value: f64| {
+            Duration::try_from_secs_f64(value)
+                .map_err(|err| Reason::InvalidConfig(format!("http.{field} = {value:?}: {err}")))
+        };
+
+        if seconds("timeout_secs", self.timeout_secs)?.is_zero() {
+            return Err(Reason::InvalidConfig("http.timeout_secs must be above 0".into()).into());
+        }
+        seconds("max_backoff_secs", self.max_backoff_secs)?;
+        // 0 turns the limit off, anything else becomes the interval between requests
+        let rate = self.requests_per_second;
+        if rate.is_nan() || (rate != 0.0 && Duration::try_from_secs_f64(1.0 / rate).is_err()) {
+            return Err(Reason::InvalidConfig(format!(
+                "http.requests_per_second = {rate:?}: must be 0 or a positive number that isn't vanishingly small"
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Loads the config file, falling back to defaults when it doesn't exist
+    pub fn load(path: &Path) -> AppResult<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let mut config: Self = toml::from_str(&std::fs::read_to_string(path)?)?;
+        config.scraper.migrate()?;
+        config.report.validate()?;
+        config.http.validate()?;
+        Ok(config)
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{
+    parser::City,
+    paths::Paths,
+    utils::{AppResult, Context, format_file_name},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Ending of the file kept next to every downloaded image, `{stem}.credit.json`
+pub const CREDIT_SUFFIX: &str = ".credit.json";
+
+/// Longest author shown in a caption or on the credits slides, some are whole paragraphs
+const MAX_AUTHOR_CHARS: usize = 40;
+
+/// Who made an image and under what terms it may be republished
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Credit {
+    /// File description page, or the URL or path an overridden image came from
+    pub source: String,
+    pub author: Option<String>,
+    /// Short name of the licence, e.g. `CC BY-SA 4.0`
+    pub licence: Option<String>,
+    pub licence_url: Option<String>,
+    /// Source the scraper picked the image from, e.g. `plwiki`, `commons` or `override`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub found_on: Option<String>,
+}
+
+fn shorten(text: &str) -> String {
+    match text.char_indices().nth(MAX_AUTHOR_CHARS) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text.to_owned(),
+    }
+}
+
+impl Credit {
+    /// Credit file of the image with `stem` in `directory`
+    pub fn path(directory: &Path, stem: &str) -> PathBuf {
+        directory.join(format!("{stem}{CREDIT_SUFFIX}"))
+    }
+
+    /// `None` when the image was downloaded before credits were kept, or put there by hand
+    pub fn load(path: &Path) -> AppResult<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let data = fs::read_to_string(path).path(path)?;
+        Ok(Some(serde_json::from_str(&data).path(path)?))
+    }
+
+    pub fn save(&self, path: &Path) -> AppResult<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?).path(path)
+    }
+
+    /// Licence without its version and port, `CC BY-SA 3.0 pl` is `CC BY-SA`
+    pub fn licence_family(&self) -> Option<String> {
+        let licence = self.licence.as_deref()?;
+        let family: Vec<&str> = licence
+            .split_whitespace()
+            .take_while(|word| !word.starts_with(|c: char| c.is_ascii_digit()))
+            .collect();
+        Some(family.join(" "))
+    }
+
+    /// Whether the licence is one of `allowed`, compared without versions, an empty list allows any
+    pub fn is_allowed(&self, allowed: &[String]) -> bool {
+        if allowed.is_empty() {
+            return true;
+        }
+
+        self.licence_family().is_some_and(|family| {
+            allowed
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(&family))
+        })
+    }
+
+    /// Short attribution like `Jan Kowalski / CC BY-SA 4.0`
+    pub fn short(&self) -> String {
+        let author = shorten(self.author.as_deref().unwrap_or("autor nieznany"));
+        match &self.licence {
+            Some(licence) => format!("{author} / {licence}"),
+            None => author,
+        }
+    }
+}
+
+/// Credits of both images of a city, `None` for images without a credit file
+pub struct CityCredits<'a> {
+    pub city: &'a City,
+    pub coa: Option<Credit>,
+    pub background: Option<Credit>,
+}
+
+impl CityCredits<'_> {
+    /// Single line for the credits slides
+    pub fn line(&self) -> String {
+        let mut line = self.city.name.clone();
+        if let Some(coa) = &self.coa {
+            let _ = write!(line, " – herb: {}", coa.short());
+        }
+        if let Some(background) = &self.background {
+            let _ = write!(line, " – fot. {}", background.short());
+        }
+        line
+    }
+}
+
+/// Credits of every city in the dataset that has at least one
+pub fn collect<'a>(
+    paths: &Paths,
+    cities: impl IntoIterator<Item = &'a City>,
+) -> AppResult<Vec<CityCredits<'a>>> {
+    let mut credits = Vec::new();
+
+    for city in cities {
+        let stem = format_file_name(city);
+        let coa = Credit::load(&Credit::path(&paths.coas, &stem))?;
+        let background = Credit::load(&Credit::path(&paths.backgrounds, &stem))?;
+
+        if coa.is_some() || background.is_some() {
+            credits.push(CityCredits {
+                city,
+                coa,
+                background,
+            });
+        }
+    }
+
+    Ok(credits)
+}
+
+/// Writes the full attribution of every image, one block per city
+pub fn write_attribution(path: &Path, credits: &[CityCredits]) -> AppResult<()> {
+    let mut text = String::new();
+
+    for city in credits {
+        let _ = writeln!(text, "{} ({})", city.city.name, city.city.identifier);
+        for (kind, credit) in [("Herb", &city.coa), ("Zdjęcie", &city.background)] {
+            let Some(credit) = credit else {
+                continue;
+            };
+
+            let _ = writeln!(
+                text,
+                "  {kind}: {}\n    Autor: {}\n    Licencja: {}{}",
+                credit.source,
+                credit.author.as_deref().unwrap_or("nieznany"),
+                credit.licence.as_deref().unwrap_or("nieznana"),
+                credit
+                    .licence_url
+                    .as_ref()
+                    .map(|url| format!(" ({url})"))
+                    .unwrap_or_default(),
+            );
+        }
+        text.push('\n');
+    }
+
+    fs::write(path, text).path(path)
+}
@@ -0,0 +1,210 @@
+//! Finds coats of arms and backgrounds that several cities ended up with.
+
+use crate::{
+    image_editor::svg_to_png,
+    log,
+    logger::LogStyle,
+    parser::Voivodeship,
+    paths::Paths,
+    queue::run_bounded,
+    utils::{AppResult, AssetKind, Context, format_file_name},
+};
+use image::{DynamicImage, imageops::FilterType};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs, panic,
+    path::{Path, PathBuf},
+};
+use tokio::task;
+
+/// How far apart the perceptual hashes of two images can be by default and still count as
+/// the same picture, out of the 64 bits
+pub const DEFAULT_MAX_DISTANCE: u32 = 5;
+
+/// Cities that got the same or nearly the same image of one kind
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Cluster {
+    /// Coats of arms are only compared with coats of arms, backgrounds with backgrounds
+    pub kind: AssetKind,
+    /// All the files are byte for byte the same, not only alike
+    pub identical: bool,
+    /// File stems of the cities
+    pub cities: Vec<String>,
+}
+
+/// Images shared between cities, to be fixed with overrides before the presentation
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DuplicateReport {
+    /// See [`DEFAULT_MAX_DISTANCE`]
+    pub max_distance: u32,
+    /// Sorted by kind, then by the cities
+    pub clusters: Vec<Cluster>,
+}
+
+impl DuplicateReport {
+    /// Writes the report as JSON
+    pub fn save(&self, path: &Path) -> AppResult<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// A downloaded image with its exact and perceptual hash
+struct Hashed {
+    stem: String,
+    kind: AssetKind,
+    sha256: [u8; 32],
+    dhash: u64,
+}
+
+/// Difference hash: a bit for every pair of neighbouring pixels of a 9x8 grayscale thumbnail,
+/// set where the left one is brighter. Survives rescaling and recompression
+fn dhash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_rgba8();
+
+    // transparent parts of coats of arms are laid on white, the way slides show them
+    let luma = |x: u32, y: u32| {
+        let [r, g, b, a] = small.get_pixel(x, y).0.map(u32::from);
+        let gray = (r * 299 + g * 587 + b * 114) / 1000;
+        (gray * a + 255 * (255 - a)) / 255
+    };
+
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash = hash << 1 | u64::from(luma(x, y) > luma(x + 1, y));
+        }
+    }
+    hash
+}
+
+fn hash_file(path: &Path) -> AppResult<([u8; 32], u64)> {
+    let bytes = fs::read(path)?;
+    let sha256 = Sha256::digest(&bytes).into();
+
+    let image = if path.extension().is_some_and(|ext| ext == "svg") {
+        image::load_from_memory(&svg_to_png(&String::from_utf8_lossy(&bytes))?)?
+    } else {
+        image::load_from_memory(&bytes)?
+    };
+
+    Ok((sha256, dhash(&image)))
+}
+
+/// Groups images of the same kind whose files match or whose hashes are at most
+/// `max_distance` bits apart from the first image of the group. Comparing with a single
+/// representative keeps a chain of slightly different images from joining unrelated cities
+fn clusters(hashed: &[Hashed], max_distance: u32) -> Vec<Cluster> {
+    let mut sorted: Vec<&Hashed> = hashed.iter().collect();
+    sorted.sort_by(|a, b| (a.kind, &a.stem).cmp(&(b.kind, &b.stem)));
+
+    let mut groups: Vec<Vec<&Hashed>> = Vec::new();
+    for image in sorted {
+        let alike = |group: &&mut Vec<&Hashed>| {
+            let representative = group[0];
+            representative.kind == image.kind
+                && (representative.sha256 == image.sha256
+                    || (representative.dhash ^ image.dhash).count_ones() <= max_distance)
+        };
+
+        match groups.iter_mut().find(alike) {
+            Some(group) => group.push(image),
+            None => groups.push(vec![image]),
+        }
+    }
+
+    let mut clusters: Vec<_> = groups
+        .into_iter()
+        .filter(|group| group.len() > 1)
+        .map(|group| {
+            let mut cities: Vec<_> = group.iter().map(|image| image.stem.clone()).collect();
+            cities.sort();
+            Cluster {
+                kind: group[0].kind,
+                identical: group.iter().all(|image| image.sha256 == group[0].sha256),
+                cities,
+            }
+        })
+        .collect();
+    clusters.sort_by(|a, b| (a.kind, &a.cities).cmp(&(b.kind, &b.cities)));
+    clusters
+}
+
+/// Hashes the coat of arms and the background of every city in the dataset and reports
+/// the ones that several cities share
+pub async fn find_duplicates(
+    paths: &Paths,
+    dataset: &[Voivodeship],
+    max_distance: u32,
+    concurrency: usize,
+) -> AppResult<DuplicateReport> {
+    let mut files: Vec<(String, AssetKind, PathBuf)> = Vec::new();
+    for city in dataset.iter().flat_map(|voivodeship| &voivodeship.content) {
+        let assets = paths.city_assets(city)?;
+        let stem = format_file_name(city);
+        if let Some(coa) = assets.coa {
+            files.push((stem.clone(), AssetKind::Coa, coa));
+        }
+        if let Some(background) = assets.background {
+            files.push((stem, AssetKind::Background, background));
+        }
+    }
+
+    log!(
+        [LogStyle::Blue],
+        "DUPLICATES",
+        "Hashing {} downloaded images...",
+        files.len()
+    );
+
+    let hashed = run_bounded(files, concurrency, |(stem, kind, path)| async move {
+        // decoding is CPU work, it would hold up the tasks sharing the worker
+        let hashed = task::spawn_blocking(move || hash_file(&path).path(&path)).await;
+        let hashed = match hashed {
+            Ok(hashed) => hashed,
+            // a panicking decoder is a bug, it shouldn't look like a broken file
+            Err(err) => panic::resume_unwind(err.into_panic()),
+        };
+
+        match hashed {
+            Ok((sha256, dhash)) => Some(Hashed {
+                stem,
+                kind,
+                sha256,
+                dhash,
+            }),
+            Err(err) => {
+                log!(
+                    Warn: [LogStyle::Yellow],
+                    "DUPLICATES",
+                    "Skipping the {kind} of {stem}: {err}"
+                );
+                None
+            }
+        }
+    })
+    .await;
+    let hashed: Vec<_> = hashed.into_iter().flatten().collect();
+
+    let clusters = clusters(&hashed, max_distance);
+    for cluster in &clusters {
+        log!(
+            Warn: [LogStyle::Yellow],
+            "DUPLICATE",
+            "{} have {} {}",
+            cluster.cities.join(", "),
+            if cluster.identical {
+                "the same"
+            } else {
+                "nearly the same"
+            },
+            cluster.kind
+        );
+    }
+
+    Ok(DuplicateReport {
+        max_distance,
+        clusters,
+    })
+}
@@ -0,0 +1,423 @@
+//! Facts about a city read from its Wikipedia article and kept in `data/facts.json`.
+
+#[cfg(feature = "scrape")]
+use crate::{config::FactParams, wiki::Infobox};
+use crate::{
+    parser::Voivodeship,
+    utils::{AppResult, format_file_name},
+};
+#[cfg(feature = "scrape")]
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "scrape")]
+use std::sync::LazyLock;
+use std::{collections::BTreeMap, path::Path};
+
+/// A value read from an infobox and where it was read from
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Fact<T> {
+    /// What was made of the parameter
+    pub value: T,
+    /// Wiki the article is on, e.g. `plwiki`
+    pub site: String,
+    /// Title of the article
+    pub page: String,
+    /// Infobox parameter the value was in
+    pub param: String,
+}
+
+/// Where the city is, shown in degrees, minutes and seconds
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Coordinates {
+    /// Degrees north, negative for south
+    pub latitude: f64,
+    /// Degrees east, negative for west
+    pub longitude: f64,
+}
+
+impl std::fmt::Display for Coordinates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dms = |degrees: f64| {
+            let seconds = (degrees.abs() * 3600.0).round() as u32;
+            format!(
+                "{}°{:02}′{:02}″",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            )
+        };
+        let north = if self.latitude < 0.0 { 'S' } else { 'N' };
+        let east = if self.longitude < 0.0 { 'W' } else { 'E' };
+
+        write!(
+            f,
+            "{}{north} {}{east}",
+            dms(self.latitude),
+            dms(self.longitude)
+        )
+    }
+}
+
+/// A few sentences from the start of a city's article, for the speaker notes
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Summary {
+    /// Plain text, see [`crate::config::ScraperConfig::summary_chars`] for its length
+    pub text: String,
+    /// Wiki the article is on, e.g. `plwiki`
+    pub site: String,
+    /// Title of the article
+    pub page: String,
+}
+
+/// What the infobox and the lead of a city's article say, every field is `None` when it
+/// wasn't there or couldn't be read
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Facts {
+    /// Year the town got its rights, the first one when it got them several times
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub town_rights: Option<Fact<i32>>,
+    /// Metres above sea level, the first number of a range
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elevation: Option<Fact<i32>>,
+    /// The first one when the city has a range of them, e.g. `58-200`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postal_code: Option<Fact<String>>,
+    /// Telephone area code without the country code, e.g. `74`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub area_code: Option<Fact<String>>,
+    /// Name of the mayor, without the party or the term
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mayor: Option<Fact<String>>,
+    /// From a `{{coord}}` template or the split parameters of Polish infoboxes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coordinates: Option<Fact<Coordinates>>,
+    /// Only fetched when [`crate::config::ScraperConfig::summary_chars`] is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Summary>,
+    /// The article had no lead to make a summary of, so it isn't asked for one again
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_lead: bool,
+}
+
+/// Facts that can be shown on a city's entry, see [`crate::config::SlidesConfig::facts`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FactKind {
+    /// `prawa miejskie 1261`
+    TownRights,
+    /// `120 m n.p.m.`
+    Elevation,
+    /// `50-001`
+    PostalCode,
+    /// `tel. +48 71`
+    AreaCode,
+    /// The mayor's name
+    Mayor,
+    /// `51°06′36″N 17°01′48″E`
+    Coordinates,
+}
+
+impl Facts {
+    /// How `kind` reads on a slide, `None` when it isn't known
+    pub fn display(&self, kind: FactKind) -> Option<String> {
+        match kind {
+            FactKind::TownRights => self
+                .town_rights
+                .as_ref()
+                .map(|fact| format!("prawa miejskie {}", fact.value)),
+            FactKind::Elevation => self
+                .elevation
+                .as_ref()
+                .map(|fact| format!("{} m n.p.m.", fact.value)),
+            FactKind::PostalCode => self.postal_code.as_ref().map(|fact| fact.value.clone()),
+            FactKind::AreaCode => self
+                .area_code
+                .as_ref()
+                .map(|fact| format!("tel. +48 {}", fact.value)),
+            FactKind::Mayor => self.mayor.as_ref().map(|fact| fact.value.clone()),
+            FactKind::Coordinates => self.coordinates.as_ref().map(|fact| fact.value.to_string()),
+        }
+    }
+}
+
+/// Facts of every city the scraper found an article for, kept between runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FactLog {
+    /// Facts by file stem of the city
+    pub cities: BTreeMap<String, Facts>,
+}
+
+impl FactLog {
+    /// Loads the facts file, an absent file means nothing was scraped yet
+    pub fn load(path: &Path) -> AppResult<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the facts file
+    pub fn save(&self, path: &Path) -> AppResult<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Puts the facts of every city in `dataset` on it, cities missing from the log get none
+    pub fn apply(&self, dataset: &mut [Voivodeship]) {
+        for city in dataset
+            .iter_mut()
+            .flat_map(|voivodeship| &mut voivodeship.content)
+        {
+            let facts = self.cities.get(&format_file_name(city));
+            city.facts = facts.cloned().unwrap_or_default();
+        }
+    }
+}
+
+/// Polish infoboxes split coordinates into parameters of their own
+#[cfg(feature = "scrape")]
+const SPLIT_COORDINATES: [[&str; 3]; 2] = [
+    ["stopni N", "minut N", "sekund N"],
+    ["stopni E", "minut E", "sekund E"],
+];
+
+/// `[[target|label]]` or `[[target]]`, the label or the target is the first group
+#[cfg(feature = "scrape")]
+static LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[\[(?:[^\]|]*\|)?([^\]]*)\]\]").unwrap());
+/// A template without templates nested in it
+#[cfg(feature = "scrape")]
+static TEMPLATE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{[^{}]*\}\}").unwrap());
+#[cfg(feature = "scrape")]
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+#[cfg(feature = "scrape")]
+static YEAR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b\d{3,4}\b").unwrap());
+/// Thousands can be separated with commas or spaces
+#[cfg(feature = "scrape")]
+static NUMBER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"-?\d{1,3}(?:[, \u{a0}]\d{3})+|-?\d+").unwrap());
+#[cfg(feature = "scrape")]
+static POSTAL_CODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b\d{2}-\d{3}\b").unwrap());
+#[cfg(feature = "scrape")]
+static AREA_CODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\d{2}").unwrap());
+/// Pronunciations and other templates the plain text can't show leave empty brackets behind
+#[cfg(feature = "scrape")]
+static EMPTY_BRACKETS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\(\s*[,;:]?\s*\)").unwrap());
+
+/// Text of a wikitext value: links become their labels, templates, tags and formatting go away
+#[cfg(feature = "scrape")]
+fn plain_text(value: &str) -> String {
+    let text = LINK.replace_all(value, "$1");
+    let text = TEMPLATE.replace_all(&text, "");
+    let text = TAG.replace_all(&text, " ");
+    text.replace("'''", "")
+        .replace("''", "")
+        .replace("&nbsp;", " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Degrees out of degrees, minutes and seconds, missing ones count as 0
+#[cfg(feature = "scrape")]
+fn degrees(parts: &[f64]) -> Option<f64> {
+    let (&degrees, rest) = parts.split_first()?;
+    let minutes = rest.first().copied().unwrap_or_default();
+    let seconds = rest.get(1).copied().unwrap_or_default();
+    Some(degrees + minutes / 60.0 + seconds / 3600.0)
+}
+
+/// Reads a `{{coord}}` template, in decimal degrees or split with `N`/`S` and `E`/`W`
+#[cfg(feature = "scrape")]
+fn coord_template(value: &str) -> Option<Coordinates> {
+    let start = value.find("{{coord").or(value.find("{{Coord"))?;
+    let template = &value[start + 2..];
+    let template = &template[..template.find("}}")?];
+
+    let mut latitude = None;
+    let mut numbers = Vec::new();
+    // named parameters and ones like `type:city` come after the coordinates
+    for part in template.split('|').skip(1).map(str::trim) {
+        match part {
+            "N" | "S" => {
+                let sign = if part == "S" { -1.0 } else { 1.0 };
+                latitude = Some(degrees(&numbers)? * sign);
+                numbers.clear();
+            }
+            "E" | "W" => {
+                let sign = if part == "W" { -1.0 } else { 1.0 };
+                return Some(Coordinates {
+                    latitude: latitude?,
+                    longitude: degrees(&numbers)? * sign,
+                });
+            }
+            _ => match part.parse() {
+                Ok(number) => numbers.push(number),
+                Err(_) => break,
+            },
+        }
+    }
+
+    match numbers[..] {
+        [latitude, longitude] => Some(Coordinates {
+            latitude,
+            longitude,
+        }),
+        _ => None,
+    }
+}
+
+/// Reads coordinates split the way Polish infoboxes do it
+#[cfg(feature = "scrape")]
+fn split_coordinates(infobox: &Infobox) -> Option<Coordinates> {
+    let [latitude, longitude] = SPLIT_COORDINATES.map(|keys| {
+        let parts: Vec<f64> = keys
+            .iter()
+            .map_while(|key| infobox.get(key)?.trim().replace(',', ".").parse().ok())
+            .collect();
+        degrees(&parts)
+    });
+
+    Some(Coordinates {
+        latitude: latitude?,
+        longitude: longitude?,
+    })
+}
+
+/// The value in the first of `keys` that `read` makes something of, with the key
+#[cfg(feature = "scrape")]
+fn first<T>(
+    infobox: &Infobox,
+    keys: &[String],
+    read: impl Fn(&str) -> Option<T>,
+) -> Option<(T, String)> {
+    keys.iter()
+        .find_map(|key| Some((read(infobox.get(key)?)?, key.clone())))
+}
+
+/// The first match of `re` in the text of `value`
+#[cfg(feature = "scrape")]
+fn find_in(re: &Regex, value: &str) -> Option<String> {
+    Some(re.find(&plain_text(value))?.as_str().to_owned())
+}
+
+/// Reads the facts `params` name out of the infobox of `page` on `site`
+#[cfg(feature = "scrape")]
+pub(crate) fn extract(infobox: &Infobox, params: &FactParams, site: &str, page: &str) -> Facts {
+    fn fact<T>(site: &str, page: &str, (value, param): (T, String)) -> Fact<T> {
+        Fact {
+            value,
+            site: site.to_owned(),
+            page: page.to_owned(),
+            param,
+        }
+    }
+
+    let town_rights = first(infobox, &params.town_rights, |value| {
+        find_in(&YEAR, value)?.parse().ok()
+    });
+    let elevation = first(infobox, &params.elevation, |value| {
+        let number = find_in(&NUMBER, value)?;
+        number.replace([',', ' ', '\u{a0}'], "").parse().ok()
+    });
+    let postal_code = first(infobox, &params.postal_code, |value| {
+        find_in(&POSTAL_CODE, value)
+    });
+    let area_code = first(infobox, &params.area_code, |value| {
+        find_in(&AREA_CODE, &value.replace("+48", ""))
+    });
+    let mayor = first(infobox, &params.mayor, |value| {
+        // a line break tends to be followed by the party or the term
+        let name = plain_text(value.split("<br").next()?);
+        Some(name).filter(|name| !name.is_empty())
+    });
+    let coordinates = first(infobox, &params.coordinates, coord_template).or_else(|| {
+        Some((
+            split_coordinates(infobox)?,
+            SPLIT_COORDINATES[0][0].to_owned(),
+        ))
+    });
+
+    Facts {
+        town_rights: town_rights.map(|found| fact(site, page, found)),
+        elevation: elevation.map(|found| fact(site, page, found)),
+        postal_code: postal_code.map(|found| fact(site, page, found)),
+        area_code: area_code.map(|found| fact(site, page, found)),
+        mayor: mayor.map(|found| fact(site, page, found)),
+        coordinates: coordinates.map(|found| fact(site, page, found)),
+        summary: None,
+        no_lead: false,
+    }
+}
+
+/// Whether the `.`, `!` or `?` at `i` ends a sentence: a capital letter follows
+/// outside of brackets and it's not the dot of an abbreviation like `r.`, `ok.`, `m.in.` or `n.p.m.`
+#[cfg(feature = "scrape")]
+fn ends_sentence(chars: &[char], i: usize) -> bool {
+    if !matches!(chars[i], '.' | '!' | '?')
+        || chars.get(i + 1) != Some(&' ')
+        || !chars.get(i + 2).is_some_and(|c| c.is_uppercase())
+    {
+        return false;
+    }
+    // the native names and pronunciations in brackets are full of abbreviations
+    let depth = chars[..i].iter().fold(0, |depth: i32, c| match c {
+        '(' => depth + 1,
+        ')' => depth - 1,
+        _ => depth,
+    });
+    if depth > 0 {
+        return false;
+    }
+    if chars[i] != '.' {
+        return true;
+    }
+
+    let word: Vec<_> = chars[..i]
+        .iter()
+        .rev()
+        .take_while(|c| !c.is_whitespace())
+        .collect();
+    let abbreviation =
+        word.contains(&&'.') || word.len() <= 3 && word.iter().all(|c| c.is_lowercase());
+    !abbreviation
+}
+
+/// Cleans up the plain text lead of an article and cuts it to at most `max_chars` characters,
+/// after the last sentence that fits or, when not even the first one does, after a word
+#[cfg(feature = "scrape")]
+pub(crate) fn summarize(lead: &str, max_chars: usize) -> Option<String> {
+    let text = lead.split_whitespace().collect::<Vec<_>>().join(" ");
+    let text = EMPTY_BRACKETS.replace_all(&text, "");
+    let text = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace(" ,", ",")
+        .replace(",,", ",")
+        .replace(" .", ".");
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() || max_chars == 0 {
+        return None;
+    }
+    if chars.len() <= max_chars {
+        return Some(text);
+    }
+
+    if let Some(end) = (0..max_chars).rev().find(|&i| ends_sentence(&chars, i)) {
+        return Some(chars[..=end].iter().collect());
+    }
+
+    // room is left for the ellipsis
+    let cut = &chars[..max_chars - 1];
+    let cut = match cut.iter().rposition(|c| c.is_whitespace()) {
+        Some(space) => &cut[..space],
+        None => cut,
+    };
+    let cut: String = cut.iter().collect();
+    Some(format!(
+        "{}…",
+        cut.trim_end_matches([',', ';', ':', '-', '–'])
+    ))
+}
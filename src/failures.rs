@@ -0,0 +1,71 @@
+//! Items that failed in the last run, what the `retry` command works from.
+
+use crate::utils::{AppResult, Failure, ReturnReport};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+};
+
+/// Items that failed in the last run, grouped by the job that failed them
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FailureLog {
+    /// Failures by [`ReturnReport::job_name`]
+    pub jobs: BTreeMap<String, Vec<Failure>>,
+}
+
+impl FailureLog {
+    /// Loads the failures file, an absent file means nothing has failed yet
+    pub fn load(path: &Path) -> AppResult<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the failures file
+    pub fn save(&self, path: &Path) -> AppResult<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Replaces the failures of the report's job with the ones from the report
+    pub fn record(&mut self, report: &ReturnReport) {
+        if report.failures.is_empty() {
+            self.jobs.remove(&report.job_name);
+        } else {
+            self.jobs
+                .insert(report.job_name.clone(), report.failures.clone());
+        }
+    }
+
+    /// Like [`FailureLog::record`] when `items` is `None`, otherwise only the failures of `items`
+    /// are replaced, the job didn't try the other ones again
+    pub fn record_for(&mut self, report: &ReturnReport, items: Option<&HashSet<String>>) {
+        let Some(items) = items else {
+            return self.record(report);
+        };
+
+        let failures = self.jobs.entry(report.job_name.clone()).or_default();
+        failures.retain(|failure| !items.contains(&failure.item));
+        failures.extend(report.failures.iter().cloned());
+        if failures.is_empty() {
+            self.jobs.remove(&report.job_name);
+        }
+    }
+
+    /// Whether nothing failed
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// File stems of every failed item, regardless of the job
+    pub fn items(&self) -> HashSet<String> {
+        self.jobs
+            .values()
+            .flatten()
+            .map(|failure| failure.item.clone())
+            .collect()
+    }
+}
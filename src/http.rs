@@ -0,0 +1,248 @@
+use crate::{
+    cache::{CacheEntry, HttpCache},
+    config::HttpConfig,
+    log,
+    logger::LogStyle,
+    utils::{AppError, AppResult, Context, Reason},
+};
+use reqwest::{
+    Response, StatusCode,
+    header::{
+        ETAG, HeaderMap, HeaderName, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+        RETRY_AFTER,
+    },
+};
+use std::{
+    error::Error,
+    hash::{BuildHasher, RandomState},
+    io::ErrorKind,
+    time::Duration,
+};
+use tokio::{
+    sync::Mutex,
+    time::{Instant, sleep, sleep_until},
+};
+
+/// Spaces requests out evenly so that at most `rate` of them start every second
+struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(rate: f64) -> Self {
+        Self {
+            interval: if rate > 0.0 {
+                Duration::from_secs_f64(1.0 / rate)
+            } else {
+                Duration::ZERO
+            },
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait(&self) {
+        if self.interval.is_zero() {
+            return;
+        }
+
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+
+        sleep_until(slot).await;
+    }
+}
+
+/// Random duration between zero and `max`, std has no RNG but its hasher seeds are random
+fn jitter(max: Duration) -> Duration {
+    let random = RandomState::new().hash_one(Instant::now());
+    max.mul_f64((random % 1000) as f64 / 1000.0)
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Timeouts, failed connections and connections dropped on the way. Other request errors,
+/// like an invalid URL or header, would fail the same way every time
+fn is_transient_error(err: &reqwest::Error) -> bool {
+    if err.is_timeout() || err.is_connect() {
+        return true;
+    }
+
+    let mut source = err.source();
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<std::io::Error>()
+            && matches!(
+                err.kind(),
+                ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe
+            )
+        {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// `Retry-After` in seconds, Wikimedia never sends the HTTP date form
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    seconds.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// HTTP client shared by every stage that talks to the network
+pub struct Http {
+    client: reqwest::Client,
+    limiter: RateLimiter,
+    retries: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    cache: HttpCache,
+}
+
+fn header(response: &Response, name: HeaderName) -> Option<String> {
+    Some(response.headers().get(name)?.to_str().ok()?.to_owned())
+}
+
+impl Http {
+    pub fn new(config: &HttpConfig, cache: HttpCache) -> AppResult<Self> {
+        // Wikimedia asks for a name, a version and a way to reach whoever runs the bot
+        let user_agent = format!(
+            "{}/{} ({}) reqwest",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            config.contact
+        );
+
+        let client = reqwest::Client::builder()
+            .user_agent(user_agent)
+            .timeout(Duration::from_secs_f64(config.timeout_secs))
+            .build()?;
+
+        Ok(Self {
+            client,
+            limiter: RateLimiter::new(config.requests_per_second),
+            retries: config.retries,
+            backoff: Duration::from_millis(config.backoff_ms),
+            max_backoff: Duration::from_secs_f64(config.max_backoff_secs),
+            cache,
+        })
+    }
+
+    /// Exponential backoff with full jitter, `attempt` starts at 1
+    fn backoff(&self, attempt: u32) -> Duration {
+        let max = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_backoff);
+        jitter(max)
+    }
+
+    /// Sends a GET request, retrying timeouts, dropped connections, 429 and 5xx responses.
+    /// Other error statuses are returned as they are for the caller to handle
+    pub async fn get(&self, url: &str) -> AppResult<Response> {
+        if self.cache.offline {
+            return Err(AppError::from(Reason::NotCached).with_url(url));
+        }
+        self.send(url, HeaderMap::new()).await
+    }
+
+    /// Body of a successful GET of `url`. A cached copy is revalidated with its ETag and
+    /// Last-Modified date and reused when the server answers 304, offline it's used as it is
+    pub async fn get_text(&self, url: &str) -> AppResult<String> {
+        let cached = self.cache.load(url)?;
+
+        if self.cache.offline {
+            return cached
+                .map(|entry| entry.body)
+                .ok_or_else(|| AppError::from(Reason::NotCached).with_url(url));
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(entry) = &cached {
+            let validators = [
+                (IF_NONE_MATCH, &entry.etag),
+                (IF_MODIFIED_SINCE, &entry.last_modified),
+            ];
+            for (name, value) in validators {
+                if let Some(value) = value.as_deref().and_then(|x| HeaderValue::from_str(x).ok()) {
+                    headers.insert(name, value);
+                }
+            }
+        }
+
+        let response = self.send(url, headers).await?;
+        if let Some(entry) = cached
+            && response.status() == StatusCode::NOT_MODIFIED
+        {
+            log!(Trace: [LogStyle::Grey], "CACHE", "{url} not modified");
+            return Ok(entry.body);
+        }
+
+        let response = response.error_for_status().url(url)?;
+        let etag = header(&response, ETAG);
+        let last_modified = header(&response, LAST_MODIFIED);
+        let body = response.text().await.url(url)?;
+
+        // stored even without validators, offline runs need it all the same
+        self.cache.store(&CacheEntry {
+            url: url.to_owned(),
+            etag,
+            last_modified,
+            body: body.clone(),
+        })?;
+        Ok(body)
+    }
+
+    /// Drops the cached copy of `url`, for bodies that turned out to be errors
+    pub fn forget(&self, url: &str) -> AppResult<()> {
+        self.cache.remove(url)
+    }
+
+    async fn send(&self, url: &str, headers: HeaderMap) -> AppResult<Response> {
+        let mut attempt = 0;
+
+        loop {
+            self.limiter.wait().await;
+            let result = self.client.get(url).headers(headers.clone()).send().await;
+
+            let (reason, retry_after) = match &result {
+                Ok(response) if is_transient_status(response.status()) => {
+                    (response.status().to_string(), retry_after(response))
+                }
+                Err(err) if is_transient_error(err) => (err.to_string(), None),
+                _ => return result.url(url),
+            };
+
+            attempt += 1;
+            if attempt > self.retries {
+                return result.url(url);
+            }
+
+            let delay = retry_after.map_or_else(
+                || self.backoff(attempt),
+                |delay| delay.min(self.max_backoff),
+            );
+            log!(
+                Debug: [LogStyle::Yellow],
+                "HTTP",
+                "{reason}, retrying {url} in {:.1} s ({attempt}/{})",
+                delay.as_secs_f32(),
+                self.retries
+            );
+            sleep(delay).await;
+        }
+    }
+}
@@ -1,44 +1,50 @@
 use crate::{
+    credits::Credit,
     log,
     logger::{LogStyle, log_msg},
     parser::Voivodeship,
     paths::Paths,
-    utils::{AppError, AppResult, ReturnReport, ensure_exists, file_stem, format_file_name},
+    progress::StageProgress,
+    queue::run_bounded,
+    utils::{
+        AppError, AppResult, Context, Failure, Reason, ReturnReport, Stage, ensure_exists,
+        file_stem, format_file_name,
+    },
 };
-use image::{DynamicImage, GenericImageView, ImageBuffer, ImageFormat, Rgba, imageops};
+use image::{DynamicImage, GenericImageView, ImageBuffer, ImageFormat, Rgba, RgbaImage, imageops};
 use resvg::{
     tiny_skia::{Pixmap, Transform},
     usvg::{Options, Tree},
 };
 use std::{
     collections::{HashMap, HashSet},
-    fs::{read_dir, read_to_string, write},
+    fs::{self, read_dir, read_to_string, write},
+    io,
     path::{Path, PathBuf},
-    sync::{
-        Arc,
-        atomic::{AtomicU32, Ordering},
-    },
+    sync::Arc,
     time,
 };
-use tokio::task::JoinSet;
-
-const CONCURRENT_JOBS: usize = 32;
 
-fn svg_to_png(svg_data: &str) -> AppResult<Vec<u8>> {
+/// Rasterizes an SVG, coats of arms often come as one
+pub fn svg_to_png(svg_data: &str) -> AppResult<Vec<u8>> {
     let tree = Tree::from_str(svg_data, &Options::default())?;
 
     let size = tree.size();
     let width = size.width() as u32;
     let height = size.height() as u32;
 
-    let mut pixmap = Pixmap::new(width, height).ok_or("Failed to create pixmap".to_owned())?;
+    let mut pixmap = Pixmap::new(width, height).ok_or(Reason::EmptyImage)?;
 
     resvg::render(&tree, Transform::identity(), &mut pixmap.as_mut());
 
-    pixmap.encode_png().map_err(|err| AppError::Io(err.into()))
+    pixmap
+        .encode_png()
+        .map_err(|err| AppError::from(io::Error::from(err)))
 }
 
-fn edit_background(input_path: &Path, output_path: &Path) -> AppResult<()> {
+/// Turns a photo into a 1920x270 entry background: cropped to the middle, darkened,
+/// blurred and framed with white lines at the top and bottom
+pub fn edit_background_image(image: &DynamicImage) -> RgbaImage {
     const BORDER_SIZE: u32 = 2;
     const CITY_WIDTH: u32 = 1920;
     const CITY_HEIGHT: u32 = 270; // hardcoded value, 1080 / 4 entries = 270 pixels per entry. Also check pres_gen for entry generation
@@ -48,13 +54,11 @@ fn edit_background(input_path: &Path, output_path: &Path) -> AppResult<()> {
     // actual content height, excluding 2px top and 2px bottom border
     const CONTENT_HEIGHT: u32 = CITY_HEIGHT - BORDER_SIZE * 2;
 
-    let mut image = image::open(input_path)?;
-
     let (orig_width, orig_height) = image.dimensions();
     let aspect_ratio = orig_height as f32 / orig_width as f32;
     let new_height = (aspect_ratio * CITY_WIDTH as f32) as u32; // most of backgrounds are not 1920x1080 hence the need to calculate new height from aspect ratio
 
-    image = image.resize_exact(
+    let mut image = image.resize_exact(
         CITY_WIDTH,
         new_height,
         image::imageops::FilterType::Lanczos3,
@@ -77,28 +81,88 @@ fn edit_background(input_path: &Path, output_path: &Path) -> AppResult<()> {
 
     imageops::replace(&mut final_img, &cropped_img, 0, BORDER_SIZE as i64); // paste the cropped image into the center, leaving 2px top and bottom
 
-    final_img.save_with_format(output_path, ImageFormat::WebP)?;
-
-    Ok(())
+    final_img
 }
 
-fn edit_coa(input_path: &Path, output_path: &Path) -> AppResult<()> {
+/// Scales a coat of arms to the size it takes on an entry
+pub fn edit_coa_image(image: &DynamicImage) -> DynamicImage {
     const TARGET_WIDTH: u32 = 176;
     const TARGET_HEIGHT: u32 = 206;
 
-    let mut image = image::open(input_path)?;
-
-    image = image.resize_exact(
+    image.resize_exact(
         TARGET_WIDTH,
         TARGET_HEIGHT,
         image::imageops::FilterType::Lanczos3,
-    );
+    )
+}
 
-    image.save_with_format(output_path, ImageFormat::WebP)?;
+fn edit_background_file(input_path: &Path, output_path: &Path) -> AppResult<()> {
+    let image = image::open(input_path)?;
+    edit_background_image(&image).save_with_format(output_path, ImageFormat::WebP)?;
+    Ok(())
+}
+
+/// Moves a spare background and its credit in place of the one that couldn't be edited
+fn promote_spare(
+    paths: &Paths,
+    stem: &str,
+    rank: usize,
+    spare: &Path,
+    replaced: &Path,
+) -> AppResult<()> {
+    let extension = spare
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("raw");
+    let promoted = paths.backgrounds.join(format!("{stem}.{extension}"));
+    fs::remove_file(replaced).path(replaced)?;
+    fs::rename(spare, &promoted).path(spare)?;
+
+    let spare_credit = Credit::path(&paths.spare_backgrounds, &format!("{stem}.{rank}"));
+    let credit = Credit::path(&paths.backgrounds, stem);
+    if spare_credit.exists() {
+        fs::rename(&spare_credit, &credit).path(&spare_credit)?;
+    } else if credit.exists() {
+        fs::remove_file(&credit).path(&credit)?;
+    }
 
     Ok(())
 }
 
+/// Edits the background, falling back to the spares in order when it can't be
+fn edit_background(paths: &Paths, input_path: &Path, output_path: &Path) -> AppResult<()> {
+    let Err(err) = edit_background_file(input_path, output_path) else {
+        return Ok(());
+    };
+
+    let stem = file_stem(input_path).unwrap_or_default();
+    for (rank, spare) in paths.spare_backgrounds_of(&stem)? {
+        match edit_background_file(&spare, output_path) {
+            Ok(()) => {
+                log!(
+                    Warn: [LogStyle::Yellow],
+                    "FALLBACK",
+                    "{stem}: {err}, using spare background {rank} instead"
+                );
+                return promote_spare(paths, &stem, rank, &spare, input_path);
+            }
+            Err(spare_err) => log!(
+                Debug: [LogStyle::Yellow],
+                "SPARE",
+                "{stem}: spare background {rank} can't be used either: {spare_err}"
+            ),
+        }
+    }
+
+    Err(err)
+}
+
+fn edit_coa(input_path: &Path, output_path: &Path) -> AppResult<()> {
+    let image = image::open(input_path)?;
+    edit_coa_image(&image).save_with_format(output_path, ImageFormat::WebP)?;
+    Ok(())
+}
+
 #[derive(Debug, Clone, Copy)]
 enum FileSet {
     Background,
@@ -106,40 +170,50 @@ enum FileSet {
 }
 
 async fn process_file(
+    paths: Arc<Paths>,
     file_path: Arc<PathBuf>,
     edited_path: Arc<PathBuf>,
     file_set: FileSet,
-    counter: Arc<AtomicU32>,
-    total: usize,
+    progress: Arc<StageProgress>,
 ) -> AppResult<()> {
     let file_stem = file_path.file_stem().unwrap().to_str().unwrap();
     let output_path = edited_path.join(format!("{file_stem}.webp"));
 
     let res = match file_set {
-        FileSet::Background => edit_background(&file_path, &output_path),
+        FileSet::Background => edit_background(&paths, &file_path, &output_path),
         FileSet::Coa => edit_coa(&file_path, &output_path),
-    };
+    }
+    .path(&file_path)
+    .city(file_stem)
+    .stage(Stage::ImageEditor);
 
-    let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+    let count = progress.advance();
+    let total = progress.total();
 
     match res {
-        Ok(()) => log!(
+        Ok(()) => log_msg(
+            progress.item_level(),
             [LogStyle::Green],
             &format!("OK{:>13}", format!("{count}/{total}",)),
-            "Saved {} to {}{output_path:?}{}",
-            match file_set {
-                FileSet::Background => "background",
-                FileSet::Coa => "COA",
-            },
-            LogStyle::Cyan,
-            LogStyle::Clear,
+            format!(
+                "Saved {} to {}{output_path:?}{}",
+                match file_set {
+                    FileSet::Background => "background",
+                    FileSet::Coa => "COA",
+                },
+                LogStyle::Cyan,
+                LogStyle::Clear,
+            ),
         ),
 
-        Err(ref e) => log!(
-            [LogStyle::Red],
-            &format!("ERR{:>12}", format!("{count}/{total}",)),
-            "Failed to process {file_stem}: {e}",
-        ),
+        Err(ref e) => {
+            progress.fail(file_stem, &e.kind);
+            log!(
+                Error: [LogStyle::Red],
+                &format!("ERR{:>12}", format!("{count}/{total}",)),
+                "Failed to process {e}",
+            )
+        }
     };
 
     res
@@ -148,7 +222,9 @@ async fn process_file(
 async fn process_file_set(
     paths: &Paths,
     dataset: &[Voivodeship],
+    only: Option<&HashSet<String>>,
     file_set: FileSet,
+    concurrency: usize,
 ) -> AppResult<ReturnReport> {
     let start_time = time::Instant::now();
     let mut stem_to_filename: HashMap<String, String> = HashMap::new();
@@ -182,9 +258,13 @@ async fn process_file_set(
     for voivodeship in dataset.iter() {
         for city in voivodeship.content.iter() {
             let stem = format_file_name(city);
-            let is_present = edited_file_stems.contains(&stem);
+            // an edited file left from before a failure mustn't keep a retried city from its turn
+            let needed = match only {
+                Some(only) => only.contains(&stem),
+                None => !edited_file_stems.contains(&stem),
+            };
 
-            if !is_present && let Some(ext) = stem_to_filename.get(&stem) {
+            if needed && let Some(ext) = stem_to_filename.get(&stem) {
                 let full_filename = format!("{stem}.{ext}");
                 let file_path = unedited_path.join(full_filename);
                 file_paths.push(file_path);
@@ -210,74 +290,83 @@ async fn process_file_set(
     for file_path in file_paths.iter_mut() {
         if file_path.extension().unwrap() == "svg" {
             let file_stem = file_stem(file_path).unwrap();
-            let svg_data = read_to_string(&file_path)?;
-            let png_data = svg_to_png(&svg_data)?;
+            let svg_data = read_to_string(&file_path).path(file_path)?;
+            let png_data = svg_to_png(&svg_data)
+                .path(file_path)
+                .stage(Stage::ImageEditor)?;
             let file_name = format!("{file_stem}.png");
             let new_path = paths.coas.join(file_name);
 
             log!(
-                [LogStyle::Blue],
+                Debug: [LogStyle::Blue],
                 "SVG",
                 "Detected SVG file, converting to PNG: {new_path:?}",
             );
 
-            write(&new_path, png_data)?;
+            write(&new_path, png_data).path(&new_path)?;
 
             *file_path = new_path;
         }
     }
 
+    let job_name = "IMAGE EDITOR: ".to_owned()
+        + match file_set {
+            FileSet::Background => "BG",
+            FileSet::Coa => "COA",
+        };
     let total = file_paths.len();
-    let counter = Arc::new(AtomicU32::new(0));
-    let file_paths: Vec<Arc<PathBuf>> = file_paths.into_iter().map(Arc::new).collect();
+    let progress = StageProgress::new(&job_name, total);
     let edited_path = Arc::new(edited_path.clone());
-
-    let mut amount_ok = 0;
-
-    for chunk in file_paths.chunks(CONCURRENT_JOBS) {
-        let mut join_set = JoinSet::new();
-        for file_path in chunk.iter() {
-            join_set.spawn(process_file(
-                file_path.clone(),
-                edited_path.clone(),
-                file_set,
-                counter.clone(),
-                total,
-            ));
+    let paths = Arc::new(paths.clone());
+
+    let failures: Vec<_> = run_bounded(file_paths, concurrency, |file_path| {
+        let file_path = Arc::new(file_path);
+        let edited_path = edited_path.clone();
+        let paths = paths.clone();
+        let progress = progress.clone();
+        async move {
+            let stem = file_stem(&file_path).unwrap_or_default();
+            let res = process_file(paths, file_path, edited_path, file_set, progress).await;
+            (stem, res)
         }
+    })
+    .await
+    .into_iter()
+    .filter_map(|(stem, res)| res.err().map(|err| Failure::new(stem, &err)))
+    .collect();
 
-        amount_ok += join_set
-            .join_all()
-            .await
-            .iter()
-            .filter(|res| res.is_ok())
-            .count();
-    }
+    progress.finish();
+
+    let amount_ok = total - failures.len();
 
     Ok(ReturnReport {
-        job_name: "IMAGE EDITOR: ".to_owned()
-            + match file_set {
-                FileSet::Background => "BG",
-                FileSet::Coa => "COA",
-            },
+        job_name,
         duration: start_time.elapsed(),
         amount_ok,
         amount_err: total - amount_ok,
+        failures,
+        overrides: vec![],
+        bytes_downloaded: 0,
     })
 }
 
+/// Edits the downloaded images that have no edited version yet, or with `only` the images of
+/// those cities whether they were edited before or not
 pub async fn process_assets(
     paths: &Paths,
     dataset: &[Voivodeship],
+    only: Option<&HashSet<String>>,
+    concurrency: usize,
 ) -> AppResult<(ReturnReport, ReturnReport)> {
     ensure_exists(&paths.backgrounds)?;
     ensure_exists(&paths.edited_backgrounds)?;
     ensure_exists(&paths.coas)?;
     ensure_exists(&paths.edited_coas)?;
 
-    let background_report = process_file_set(paths, dataset, FileSet::Background).await?;
+    let background_report =
+        process_file_set(paths, dataset, only, FileSet::Background, concurrency).await?;
     log!([LogStyle::Purple], "JOB DONE", "{background_report}");
-    let coa_report = process_file_set(paths, dataset, FileSet::Coa).await?;
+    let coa_report = process_file_set(paths, dataset, only, FileSet::Coa, concurrency).await?;
     log!([LogStyle::Purple], "JOB DONE", "{coa_report}");
 
     Ok((background_report, coa_report))
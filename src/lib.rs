@@ -0,0 +1,112 @@
+//! Generates slides presenting every Polish city: its coat of arms, a photo and basic stats.
+//!
+//! The pipeline is made of stages that can be used on their own:
+//!
+//! - loading the dataset: [`parse_csv`] or [`parse_dataset`]
+//! - resolving where the assets of a city live: [`Paths`] and [`Paths::city_assets`]
+//! - scraping Wikipedia for coats of arms and backgrounds, only as part of [`run_pipeline`]
+//! - editing images: [`edit_background_image`] and [`edit_coa_image`] work in memory,
+//!   [`process_assets`] edits every downloaded file
+//! - rendering slides: [`SlideRenderer`] returns in-memory images,
+//!   [`generate_slides`] writes the whole presentation to disk
+//!
+//! [`run_pipeline`] runs all of them in order, the way the `geo-pres` binary does. Errors and
+//! stage reports are [`AppError`] and [`ReturnReport`], settings come from [`config::Config`].
+//! Everything else the binary is built from, the scraper, the HTTP client and the on-disk
+//! bookkeeping, is internal and can change between releases.
+//!
+//! Stages are behind cargo features, so a build that only renders cached assets doesn't pull in
+//! the network stack:
+//!
+//! - `scrape`: the scraper, brings in `reqwest` and TLS
+//! - `render`: image editing and in-memory rendering
+//! - `export-webp`, `export-png`: writing slides to disk in the given format, implies `render`
+//!
+//! `scrape` and `export-webp` are enabled by default.
+//!
+//! ```no_run
+//! # #[cfg(feature = "render")]
+//! # {
+//! use geo_pres::{Paths, SlideRenderer, parse_csv};
+//!
+//! let paths = Paths::from_data_dir("data");
+//! let dataset = parse_csv(&paths.dataset)?;
+//!
+//! let renderer = SlideRenderer::load(&paths)?;
+//! let entry = renderer.entry(&dataset[0].content[0])?;
+//! entry.save("entry.png")?;
+//! # }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+#![warn(missing_docs)]
+#[cfg(feature = "scrape")]
+mod cache;
+#[cfg(feature = "scrape")]
+mod candidates;
+pub mod config;
+// scraping writes credits and exporting reads them
+#[cfg_attr(
+    not(all(
+        feature = "scrape",
+        any(feature = "export-webp", feature = "export-png")
+    )),
+    allow(dead_code)
+)]
+mod credits;
+#[cfg(feature = "render")]
+pub mod duplicates;
+pub mod facts;
+pub mod failures;
+#[cfg(feature = "scrape")]
+mod http;
+#[cfg(feature = "render")]
+mod image_editor;
+pub mod logger;
+#[cfg(feature = "scrape")]
+mod manifest;
+#[cfg(feature = "scrape")]
+mod overrides;
+mod parser;
+mod paths;
+mod pipeline;
+// only the bars are left without any stage compiled in
+#[cfg_attr(not(any(feature = "scrape", feature = "render")), allow(dead_code))]
+mod progress;
+#[cfg(any(feature = "scrape", feature = "render"))]
+mod queue;
+pub mod report;
+#[cfg(feature = "scrape")]
+mod scraper;
+// the credits and the numbering are only needed for exporting
+#[cfg(feature = "render")]
+#[cfg_attr(
+    not(any(feature = "export-webp", feature = "export-png")),
+    allow(dead_code)
+)]
+mod slides_gen;
+mod utils;
+#[cfg(feature = "scrape")]
+mod validate;
+#[cfg(feature = "scrape")]
+mod verify;
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+pub mod watch;
+#[cfg(feature = "scrape")]
+mod wiki;
+
+#[cfg(feature = "render")]
+pub use image_editor::{edit_background_image, edit_coa_image, process_assets, svg_to_png};
+pub use parser::{City, VOIVODESHIP_COUNT, Voivodeship, parse_csv, parse_dataset};
+pub use paths::{CityAssets, Paths};
+pub use pipeline::{PipelineReports, ScrapeOptions, SuffixStrategy, run_pipeline};
+#[cfg(feature = "render")]
+pub use slides_gen::{CITIES_PER_SLIDE, Slide, SlideRenderer};
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+pub use slides_gen::{
+    SLIDE_FORMAT, generate_city_slides, generate_slides, generate_voivodeship_slides,
+};
+pub use utils::{
+    AppError, AppResult, AssetKind, Context, ErrorContext, ErrorKind, ErrorReport, Failure,
+    OverrideUse, Reason, ReturnReport, Stage,
+};
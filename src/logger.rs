@@ -1,4 +1,21 @@
+//! Log output of the pipeline: levels, colours, the log file and JSON lines.
+//! Set it up with [`init`] before running any stage, [`crate::log!`] writes to it.
+
+use crate::progress;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// ANSI style of a piece of a log line, displays as nothing when colours are off
 #[derive(Clone, Copy)]
+// the variants are named after the SGR codes they stand for
+#[allow(missing_docs)]
 pub enum LogStyle {
     Clear = 0,
     Bold = 1,
@@ -14,23 +31,170 @@ pub enum LogStyle {
 
 impl std::fmt::Display for LogStyle {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "\x1b[{}m", *self as usize)
+        if colors_enabled() {
+            write!(f, "\x1b[{}m", *self as usize)?;
+        }
+        Ok(())
     }
 }
 
 impl From<LogStyle> for String {
     fn from(value: LogStyle) -> Self {
-        format!("\x1b[{}m", value as usize)
+        value.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// How much gets logged, every level includes the ones before it
+pub enum LogLevel {
+    /// Only what stops an item or the whole run
+    Error,
+    /// Also fallbacks and results that look wrong
+    Warn,
+    /// Also the progress of every stage, the default
+    Info,
+    /// Also every page and file that was tried
+    Debug,
+    /// Also every request
+    Trace,
+}
+
+impl LogLevel {
+    /// Moves the default `Info` level up by `verbose` steps and down by `quiet` steps
+    pub fn from_verbosity(verbose: u8, quiet: u8) -> Self {
+        match 2 + verbose as i16 - quiet as i16 {
+            ..=0 => Self::Error,
+            1 => Self::Warn,
+            2 => Self::Info,
+            3 => Self::Debug,
+            _ => Self::Trace,
+        }
+    }
+}
+
+/// How log lines are written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// See [`init`]
+pub struct LoggerConfig {
+    /// Lines above it are dropped
+    pub level: LogLevel,
+    /// Applies to the terminal and the file alike
+    pub format: LogFormat,
+    /// Appended to, on top of the terminal
+    pub file: Option<PathBuf>,
+}
+
+impl Default for LoggerConfig {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::Info,
+            format: LogFormat::Text,
+            file: None,
+        }
     }
 }
 
-pub(crate) fn log_msg<const N: usize>(colors: [LogStyle; N], prefix: &str, message: String) {
+struct Logger {
+    level: LogLevel,
+    format: LogFormat,
+    colors: bool,
+    bars: bool,
+    file: Option<Mutex<File>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+impl Logger {
+    fn new(config: LoggerConfig) -> io::Result<Self> {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+
+        let file = match config.file {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => None,
+        };
+
+        let text_terminal = config.format == LogFormat::Text && io::stdout().is_terminal();
+
+        Ok(Self {
+            level: config.level,
+            format: config.format,
+            colors: text_terminal && !no_color,
+            bars: text_terminal && config.level >= LogLevel::Info,
+            file,
+        })
+    }
+}
+
+fn logger() -> &'static Logger {
+    LOGGER.get_or_init(|| {
+        Logger::new(LoggerConfig::default()).expect("default logger has no file to open")
+    })
+}
+
+/// Sets up the global logger, has to be called before anything gets logged to take effect
+pub fn init(config: LoggerConfig) -> io::Result<()> {
+    let logger = Logger::new(config)?;
+    // ignoring the error is fine, it only means that something was logged before init
+    let _ = LOGGER.set(logger);
+    Ok(())
+}
+
+/// Whether [`LogStyle`]s are written, only to a terminal and when `NO_COLOR` isn't set
+pub fn colors_enabled() -> bool {
+    logger().colors
+}
+
+/// Whether stages show progress bars instead of a line per item
+pub fn progress_bars_enabled() -> bool {
+    logger().bars
+}
+
+fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip the whole CSI sequence, all of ours end with 'm'
+            for c in chars.by_ref() {
+                if c == 'm' {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+
+    out
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    timestamp_ms: u128,
+    level: LogLevel,
+    target: &'a str,
+    message: &'a str,
+}
+
+fn format_text<const N: usize>(colors: [LogStyle; N], prefix: &str, message: &str) -> String {
     let lines: Vec<_> = message.lines().collect();
     let Some((&first, rest)) = lines.split_first() else {
-        return;
+        return String::new();
     };
 
-    println!(
+    let mut out = format!(
         "[{}{prefix:15}{}] {first}",
         colors
             .iter()
@@ -40,13 +204,67 @@ pub(crate) fn log_msg<const N: usize>(colors: [LogStyle; N], prefix: &str, messa
     );
 
     for &line in rest {
-        println!("................. {line}",);
+        out.push_str("\n................. ");
+        out.push_str(line);
+    }
+
+    out
+}
+
+fn format_json(level: LogLevel, prefix: &str, message: &str) -> String {
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+
+    serde_json::to_string(&JsonLine {
+        timestamp_ms,
+        level,
+        target: prefix.trim(),
+        message: &strip_ansi(message),
+    })
+    .expect("log lines always serialize")
+}
+
+/// Backs the [`log!`](crate::log) macro, prefer the macro over calling this directly
+pub fn log_msg<const N: usize>(
+    level: LogLevel,
+    colors: [LogStyle; N],
+    prefix: &str,
+    message: String,
+) {
+    let logger = logger();
+    if level > logger.level || message.lines().next().is_none() {
+        return;
+    }
+
+    let line = match logger.format {
+        LogFormat::Text => format_text(colors, prefix, &message),
+        LogFormat::Json => format_json(level, prefix, &message),
+    };
+
+    progress::suspend(|| println!("{line}"));
+
+    if let Some(file) = &logger.file {
+        let mut file = file.lock().unwrap_or_else(|err| err.into_inner());
+        // a broken log file shouldn't take the whole run down with it
+        let _ = writeln!(file, "{}", strip_ansi(&line));
     }
 }
 
+/// Logs a message at `Info` level, or at the level given before a colon:
+/// `log!(Debug: [LogStyle::Grey], "PREFIX", "...")`
 #[macro_export]
 macro_rules! log {
+    ($level:ident: $colors:expr, $prefix:expr, $($arg:tt)*) => {
+        $crate::logger::log_msg(
+            $crate::logger::LogLevel::$level,
+            $colors,
+            $prefix,
+            format_args!($($arg)*).to_string(),
+        )
+    };
     ($colors:expr, $prefix:expr, $($arg:tt)*) => {
-        log_msg($colors, $prefix, format_args!($($arg)*).to_string())
+        $crate::log!(Info: $colors, $prefix, $($arg)*)
     };
 }
@@ -1,20 +1,23 @@
-use crate::{
-    image_editor::process_assets,
-    logger::{LogStyle, log_msg},
-    parser::{Voivodeship, parse_csv},
-    paths::Paths,
-    scraper::{download_assets, get_links},
-    slides_gen::generate_slides,
-    utils::AppResult,
+use crate::cli::{Args, Command};
+use clap::Parser;
+#[cfg(feature = "render")]
+use geo_pres::duplicates::{DEFAULT_MAX_DISTANCE, find_duplicates};
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+use geo_pres::watch::watch;
+use geo_pres::{
+    AppResult, Context, Paths, Reason, ReturnReport, ScrapeOptions, Stage, Voivodeship,
+    config::Config,
+    facts::FactLog,
+    failures::FailureLog,
+    log,
+    logger::{self, LogStyle},
+    parse_csv,
+    report::RunReport,
+    run_pipeline,
 };
+use std::process::ExitCode;
 
-mod image_editor;
-mod logger;
-mod parser;
-mod paths;
-mod scraper;
-mod slides_gen;
-mod utils;
+mod cli;
 
 fn display_dataset(paths: &Paths, dataset: &[Voivodeship]) {
     let table_header = format!(
@@ -41,6 +44,9 @@ fn display_dataset(paths: &Paths, dataset: &[Voivodeship]) {
                 "{:<23} {:<24} {:>10} {:>12}",
                 city.name, city.powiat, city.total_population, city.area_km
             ));
+            if let Some(summary) = &city.facts.summary {
+                rows.push(format!("    {}", summary.text));
+            }
         }
     }
 
@@ -49,35 +55,186 @@ fn display_dataset(paths: &Paths, dataset: &[Voivodeship]) {
 }
 
 #[tokio::main]
-async fn main() -> AppResult<()> {
-    let paths = Paths::new()?;
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    if let Err(err) = logger::init(args.logger_config()) {
+        eprintln!("Couldn't set up logging: {err}");
+        return ExitCode::FAILURE;
+    }
 
-    let dataset = parse_csv(&paths.dataset)?;
-    display_dataset(&paths, &dataset);
+    match run(args).await {
+        Ok(code) => code,
+        Err(err) => {
+            log!(
+                Error: [LogStyle::Bold, LogStyle::Red],
+                "CRITICAL ERROR",
+                "{err}"
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    let (scraper_report, links) = get_links(&paths, &dataset).await?;
-    log!([LogStyle::Purple], "JOB DONE", "{scraper_report}");
+async fn run(args: Args) -> AppResult<ExitCode> {
+    let mut paths = Paths::new()?;
+    if let Some(config) = args.config {
+        paths.config = config;
+    }
+    if let Some(report) = args.report {
+        paths.report = report;
+    }
+    if let Some(failures) = args.failures {
+        paths.failures = failures;
+    }
+    if let Some(overrides) = args.overrides {
+        paths.overrides = overrides;
+    }
 
-    let downloader_report = download_assets(links, &paths).await?;
-    log!([LogStyle::Purple], "JOB DONE", "{downloader_report}");
+    let mut config = Config::load(&paths.config)
+        .path(&paths.config)
+        .stage(Stage::Setup)?;
+    config.http.offline |= args.offline;
 
-    let (background_edit_report, coa_edit_report) = process_assets(&paths, &dataset).await?;
+    let mut failures = FailureLog::load(&paths.failures)
+        .path(&paths.failures)
+        .stage(Stage::Setup)?;
 
-    let slides_gen_report = generate_slides(&paths, &dataset)?;
+    let mut dataset = parse_csv(&paths.dataset)?;
+    FactLog::load(&paths.facts)
+        .path(&paths.facts)
+        .stage(Stage::Setup)?
+        .apply(&mut dataset);
 
-    let total = scraper_report.clone()
-        + downloader_report.clone()
-        + background_edit_report.clone()
-        + coa_edit_report.clone()
-        + slides_gen_report.clone();
+    let options = match args.command {
+        None | Some(Command::Run) => {
+            display_dataset(&paths, &dataset);
+            ScrapeOptions::default()
+        }
+        Some(Command::Retry(retry)) => {
+            if failures.is_empty() {
+                log!(
+                    [LogStyle::Green],
+                    "RETRY",
+                    "No failures recorded in {:?}, nothing to retry",
+                    paths.failures
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let items = failures.items();
+            log!(
+                [LogStyle::Blue],
+                "RETRY",
+                "Retrying {} failed {}",
+                items.len(),
+                if items.len() == 1 { "item" } else { "items" }
+            );
+
+            ScrapeOptions {
+                only: Some(items),
+                strategy: retry.suffixes,
+                extra_suffixes: retry.extra_suffixes,
+                download_only: false,
+            }
+        }
+        Some(Command::Download) => {
+            if !cfg!(feature = "scrape") {
+                return Err(Reason::MissingFeature("scrape").into());
+            }
+
+            ScrapeOptions {
+                download_only: true,
+                ..Default::default()
+            }
+        }
+        #[cfg(feature = "render")]
+        Some(Command::Duplicates(duplicates)) => {
+            let report = find_duplicates(
+                &paths,
+                &dataset,
+                duplicates.max_distance.unwrap_or(DEFAULT_MAX_DISTANCE),
+                config.concurrency.image_editor,
+            )
+            .await?;
+            report.save(&paths.duplicates).path(&paths.duplicates)?;
+
+            log!(
+                [LogStyle::Purple],
+                "DUPLICATES",
+                "Found {} {}, saved to {:?}",
+                report.clusters.len(),
+                if report.clusters.len() == 1 {
+                    "group of cities sharing an image"
+                } else {
+                    "groups of cities sharing an image"
+                },
+                paths.duplicates
+            );
+            return Ok(ExitCode::SUCCESS);
+        }
+        #[cfg(not(feature = "render"))]
+        Some(Command::Duplicates(_)) => {
+            return Err(Reason::MissingFeature("render").into());
+        }
+        #[cfg(any(feature = "export-webp", feature = "export-png"))]
+        Some(Command::Watch) => {
+            watch(&paths, config, dataset, failures, args.offline).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        #[cfg(not(any(feature = "export-webp", feature = "export-png")))]
+        Some(Command::Watch) => {
+            return Err(Reason::MissingFeature("export-webp").into());
+        }
+    };
+
+    let reports = run_pipeline(&paths, &config, &dataset, &options, &mut failures)
+        .await?
+        .to_vec();
+
+    let total = reports
+        .iter()
+        .cloned()
+        .fold(ReturnReport::default(), |total, report| total + report);
+
+    let run_report = RunReport::new(
+        reports.clone(),
+        args.max_error_rate.unwrap_or(config.report.max_error_rate),
+    );
+    run_report.save(&paths.report)?;
 
     log!(
         [LogStyle::Purple, LogStyle::Bold],
         "FINISHED",
-        "Finished processing. Stats:\n{0}\n{scraper_report}\n{downloader_report}\n{background_edit_report}\n{coa_edit_report}\n{slides_gen_report}\n{0}\n{total}",
+        "Finished processing. Stats:\n{0}\n{1}\n{0}\n{total}",
         "=".repeat(60),
+        reports
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n"),
     );
 
+    if !total.overrides.is_empty() {
+        log!(
+            [LogStyle::Yellow],
+            "FINISHED",
+            "Used overrides from {:?} for {} {}: {}",
+            paths.overrides,
+            total.overrides.len(),
+            if total.overrides.len() == 1 {
+                "city"
+            } else {
+                "cities"
+            },
+            total
+                .overrides
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+    }
+
     log!(
         [LogStyle::Bold],
         "FINISHED",
@@ -86,5 +243,25 @@ async fn main() -> AppResult<()> {
         LogStyle::Clear,
     );
 
-    Ok(())
+    if !failures.is_empty() {
+        log!(
+            [LogStyle::Yellow],
+            "FINISHED",
+            "Failed items were saved to {:?}, run with 'retry' to try them again",
+            paths.failures,
+        );
+    }
+
+    if !run_report.passed {
+        log!(
+            Warn: [LogStyle::Red, LogStyle::Bold],
+            "FINISHED",
+            "Error rate {:.2}% exceeds the allowed {:.2}%, see {:?}",
+            run_report.error_rate * 100.0,
+            run_report.max_error_rate * 100.0,
+            paths.report,
+        );
+    }
+
+    Ok(run_report.exit_code())
 }
@@ -0,0 +1,234 @@
+use crate::{
+    paths::files_by_stem,
+    scraper::{ImageLink, Links},
+    utils::{AppResult, AssetKind, file_stem},
+    validate::{check_whole, write_atomic},
+    verify::MatchRecord,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Seconds since the Unix epoch
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// Lowercase hex SHA-256 of `bytes`, how the manifest identifies a file's contents
+pub fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// A file that was saved for an asset
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Download {
+    /// Relative to the data directory
+    pub path: PathBuf,
+    /// URL or override file the bytes came from
+    pub from: String,
+    pub downloaded_at: u64,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Download {
+    pub fn new(path: PathBuf, from: String, bytes: &[u8]) -> Self {
+        Self {
+            path,
+            from,
+            downloaded_at: unix_time(),
+            size: bytes.len() as u64,
+            sha256: sha256(bytes),
+        }
+    }
+}
+
+/// Where one asset of a city was resolved to and what became of it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AssetEntry {
+    /// See [`crate::verify::AssetMatch`]
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<String>,
+    pub scraped_at: u64,
+    /// The image to download first, backgrounds are followed by their spares
+    pub links: Vec<ImageLink>,
+    /// `None` until the download succeeds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download: Option<Download>,
+    /// Runner-up backgrounds saved with the download, the image editor may put one in its place
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spares: Vec<Download>,
+}
+
+impl AssetEntry {
+    /// Whether the download is still to be done, or no whole file of the city is left.
+    /// Looked up by stem, the extension follows the format of the bytes, and whatever it holds
+    /// counts, a file replaced or cropped by hand isn't downloaded over
+    fn is_pending(&self, data: &Path) -> bool {
+        let Some(download) = &self.download else {
+            return true;
+        };
+
+        let path = data.join(&download.path);
+        let (Some(directory), Some(stem)) = (path.parent(), file_stem(&path)) else {
+            return true;
+        };
+        let files = files_by_stem(directory, &stem).unwrap_or_default();
+        !files
+            .iter()
+            .any(|file| fs::read(file).is_ok_and(|bytes| check_whole(&bytes).is_ok()))
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CityEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coa: Option<AssetEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<AssetEntry>,
+}
+
+impl CityEntry {
+    pub fn asset(&self, kind: AssetKind) -> Option<&AssetEntry> {
+        match kind {
+            AssetKind::Coa => self.coa.as_ref(),
+            AssetKind::Background => self.background.as_ref(),
+        }
+    }
+
+    fn asset_mut(&mut self, kind: AssetKind) -> &mut Option<AssetEntry> {
+        match kind {
+            AssetKind::Coa => &mut self.coa,
+            AssetKind::Background => &mut self.background,
+        }
+    }
+}
+
+/// Every resolved link and download, so that downloading can be done separately from scraping
+/// and every file can be traced back to where it came from
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub cities: BTreeMap<String, CityEntry>,
+}
+
+impl Manifest {
+    /// Loads the manifest file, an absent file means nothing was scraped yet
+    pub fn load(path: &Path) -> AppResult<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Writes through a temporary file, an interrupted run keeps the previous manifest
+    pub fn save(&self, path: &Path) -> AppResult<()> {
+        write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// Replaces the kinds `links` has with freshly scraped ones, dropping their old downloads
+    pub fn record_links(&mut self, stem: &str, links: &Links, record: &MatchRecord) {
+        let city = self.cities.entry(stem.to_owned()).or_default();
+        let found = [
+            (AssetKind::Coa, links.coa.iter().cloned().collect()),
+            (AssetKind::Background, links.bg.clone()),
+        ];
+
+        for (kind, links) in found {
+            if links.is_empty() {
+                continue;
+            }
+
+            let matched = record.assets.get(&kind);
+            *city.asset_mut(kind) = Some(AssetEntry {
+                source: matched.map_or_else(String::new, |matched| matched.source.clone()),
+                page: matched.and_then(|matched| matched.title.clone()),
+                scraped_at: unix_time(),
+                links,
+                download: None,
+                spares: vec![],
+            });
+        }
+    }
+
+    pub fn record_download(
+        &mut self,
+        stem: &str,
+        kind: AssetKind,
+        download: Download,
+        spares: Vec<Download>,
+    ) {
+        let entry = self
+            .cities
+            .get_mut(stem)
+            .and_then(|city| city.asset_mut(kind).as_mut());
+        if let Some(entry) = entry {
+            entry.download = Some(download);
+            entry.spares = spares;
+        }
+    }
+
+    /// Sizes every saved file may have, by its path under `data`. A background may also be
+    /// one of its spares, which keeps its own extension when it's promoted
+    pub fn sizes(&self, data: &Path) -> HashMap<PathBuf, Vec<u64>> {
+        let mut sizes: HashMap<PathBuf, Vec<u64>> = HashMap::new();
+        let entries = self
+            .cities
+            .values()
+            .flat_map(|city| [&city.coa, &city.background])
+            .flatten();
+
+        for entry in entries {
+            let Some(download) = &entry.download else {
+                continue;
+            };
+            let path = data.join(&download.path);
+            for spare in &entry.spares {
+                if let Some(extension) = spare.path.extension() {
+                    sizes
+                        .entry(path.with_extension(extension))
+                        .or_default()
+                        .push(spare.size);
+                }
+            }
+            sizes.entry(path).or_default().push(download.size);
+        }
+
+        sizes
+    }
+
+    /// Links of the assets that haven't been downloaded, of the `only` cities when given
+    pub fn pending(&self, data: &Path, only: Option<&HashSet<String>>) -> Vec<(String, Links)> {
+        self.cities
+            .iter()
+            .filter(|(stem, _)| only.is_none_or(|only| only.contains(*stem)))
+            .filter_map(|(stem, city)| {
+                let pending = |entry: &Option<AssetEntry>| {
+                    entry
+                        .as_ref()
+                        .filter(|entry| entry.is_pending(data))
+                        .map(|entry| entry.links.clone())
+                        .unwrap_or_default()
+                };
+
+                let links = Links {
+                    coa: pending(&city.coa).into_iter().next(),
+                    bg: pending(&city.background),
+                };
+                (links.coa.is_some() || !links.bg.is_empty()).then(|| (stem.clone(), links))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{parser::City, utils::AppResult};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path, path::PathBuf};
+
+/// Where an overridden image comes from
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageSource {
+    /// Downloaded like any scraped image
+    Url(String),
+    /// Copied as it is, a relative path starts in the data directory
+    File(PathBuf),
+}
+
+/// Manual fixes for one city, every field is optional and replaces only its own part
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Override {
+    /// Article to read the images from, instead of the Wikidata item and the guessed titles
+    pub article: Option<String>,
+    pub coa: Option<ImageSource>,
+    pub background: Option<ImageSource>,
+}
+
+impl Override {
+    /// Names of the parts that are set, for the run summary
+    pub fn fields(&self) -> Vec<String> {
+        [
+            ("article", self.article.is_some()),
+            ("coa", self.coa.is_some()),
+            ("background", self.background.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| name.to_owned())
+        .collect()
+    }
+}
+
+/// Overrides file, a table per city keyed by its TERYT identifier or by its name:
+///
+/// ```toml
+/// ["226401 1"]
+/// article = "Sopot"
+/// background = { url = "https://upload.wikimedia.org/wikipedia/commons/a/ab/Sopot.jpg" }
+///
+/// [Bardo]
+/// coa = { file = "my-images/bardo.svg" }
+/// ```
+#[derive(Debug, Default)]
+pub struct Overrides {
+    by_teryt: HashMap<String, Override>,
+    by_name: HashMap<String, Override>,
+}
+
+/// `226401 1`, `226401_1` and `2264011` are all the same identifier
+fn teryt_digits(key: &str) -> Option<String> {
+    let digits: String = key.chars().filter(|c| !matches!(c, ' ' | '_')).collect();
+    (!digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())).then_some(digits)
+}
+
+impl Overrides {
+    /// Loads the overrides file, an absent file means there's nothing to override
+    pub fn load(path: &Path) -> AppResult<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let table: HashMap<String, Override> = toml::from_str(&std::fs::read_to_string(path)?)?;
+        let mut overrides = Self::default();
+        for (key, value) in table {
+            match teryt_digits(&key) {
+                Some(teryt) => overrides.by_teryt.insert(teryt, value),
+                None => overrides.by_name.insert(key, value),
+            };
+        }
+
+        Ok(overrides)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_teryt.len() + self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Override of `city`, one keyed by the identifier wins over one keyed by a name
+    /// since the name may be shared with other cities
+    pub fn get(&self, city: &City) -> Option<&Override> {
+        teryt_digits(&city.identifier)
+            .and_then(|teryt| self.by_teryt.get(&teryt))
+            .or_else(|| self.by_name.get(&city.name))
+    }
+}
@@ -1,24 +1,37 @@
 use crate::{
+    facts::Facts,
     log,
-    logger::{LogStyle, log_msg},
-    utils::{AppError, AppResult},
+    logger::LogStyle,
+    utils::{AppError, AppResult, Context, Reason, Stage},
 };
 use regex::Regex;
 use std::{cmp::Reverse, io, num::ParseIntError, path::Path};
 
+/// Poland has 16 voivodeships, the dataset has to list all of them
 pub const VOIVODESHIP_COUNT: usize = 16;
 const DATA_COLUMNS: usize = 7;
 
+/// A row of the dataset
 #[derive(Clone, Debug)]
 pub struct City {
+    /// TERYT code of the municipality, e.g. `226401 1`
     pub identifier: String,
+    /// As written in the dataset, with Polish letters
     pub name: String,
+    /// Lowercased, a city with powiat rights is its own powiat
     pub powiat: String,
+    /// Area in hectares
     pub area_ha: u64,
+    /// Area in square kilometres
     pub area_km: u64,
+    /// Number of inhabitants
     pub total_population: u64,
+    /// People per square kilometre
     pub population_per_km: u64,
+    /// Name of the voivodeship the city is listed under
     pub voivodeship: String,
+    /// Read from the city's article by the scraper, see [`crate::facts::FactLog::apply`]
+    pub facts: Facts,
 }
 
 impl TryFrom<([&str; DATA_COLUMNS], String)> for City {
@@ -36,20 +49,39 @@ impl TryFrom<([&str; DATA_COLUMNS], String)> for City {
             total_population: data[5].parse()?,
             population_per_km: data[6].parse()?,
             voivodeship,
+            facts: Facts::default(),
         })
     }
 }
 
+/// A voivodeship with its totals and cities, see [`parse_csv`]
+#[derive(Clone)]
 pub struct Voivodeship {
+    /// Lowercased and without the `WOJ.` prefix, e.g. `dolnośląskie`
     pub name: String,
+    /// Area in hectares
     pub area_ha: u64,
+    /// Area in square kilometres
     pub area_km: u64,
+    /// Number of inhabitants
     pub total_population: u64,
+    /// People per square kilometre
     pub population_per_km: u64,
+    /// Cities, the most populous first
     pub content: Vec<City>,
 }
 
+/// Loads the dataset from a CSV file, voivodeships sorted by population and cities within them too
 pub fn parse_csv(path: &Path) -> AppResult<[Voivodeship; VOIVODESHIP_COUNT]> {
+    read_dataset(path).stage(Stage::Parser).path(path)
+}
+
+/// Parses a dataset that's already in memory, in the same CSV format as `dane.csv`
+pub fn parse_dataset(data: &str) -> AppResult<[Voivodeship; VOIVODESHIP_COUNT]> {
+    parse_rows(data).stage(Stage::Parser)
+}
+
+fn read_dataset(path: &Path) -> AppResult<[Voivodeship; VOIVODESHIP_COUNT]> {
     log!(
         [LogStyle::Blue, LogStyle::Bold],
         "PARSER",
@@ -58,7 +90,10 @@ pub fn parse_csv(path: &Path) -> AppResult<[Voivodeship; VOIVODESHIP_COUNT]> {
     );
 
     let data = std::fs::read_to_string(path)?;
+    parse_rows(&data)
+}
 
+fn parse_rows(data: &str) -> AppResult<[Voivodeship; VOIVODESHIP_COUNT]> {
     log!(
         [LogStyle::Blue, LogStyle::Bold],
         "PARSER",
@@ -81,7 +116,7 @@ pub fn parse_csv(path: &Path) -> AppResult<[Voivodeship; VOIVODESHIP_COUNT]> {
         if parts[2].is_empty() && !parts[1].is_empty() {
             current_voivodeship += 1;
             let Some(caps) = name_re.captures(parts[1].trim()) else {
-                return Err(AppError::Other("No voivodeship name found".into()));
+                return Err(Reason::NoVoivodeshipName.into());
             };
 
             dataset[current_voivodeship as usize] = Some(Voivodeship {
@@ -101,9 +136,9 @@ pub fn parse_csv(path: &Path) -> AppResult<[Voivodeship; VOIVODESHIP_COUNT]> {
             .name
             .clone();
 
-        let city: City = (parts, voivodeship_name)
-            .try_into()
-            .map_err(|err| AppError::Io(io::Error::new(io::ErrorKind::InvalidData, err)))?;
+        let city: City = (parts, voivodeship_name).try_into().map_err(|err| {
+            AppError::from(io::Error::new(io::ErrorKind::InvalidData, err)).with_city(parts[1])
+        })?;
 
         let cell = &mut dataset[current_voivodeship as usize];
         cell.as_mut().unwrap().content.push(city);
@@ -1,21 +1,65 @@
-use crate::utils::{AppError, AppResult};
-use std::{io, path::PathBuf};
+use crate::{
+    credits::Credit,
+    parser::City,
+    utils::{AppError, AppResult, Context, Stage, file_stem, format_file_name},
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 
+/// Where every input and output of the pipeline lives, see [`Paths::from_data_dir`]
 #[derive(Clone)]
 pub struct Paths {
+    /// CSV the cities come from, see [`crate::parse_csv`]
     pub dataset: PathBuf,
+    /// Downloaded coats of arms, named `{TERYT}+{name}` with underscores for spaces
     pub coas: PathBuf,
+    /// Coats of arms scaled for the slides, always WebP
     pub edited_coas: PathBuf,
+    /// Downloaded backgrounds, named like the coats of arms
     pub backgrounds: PathBuf,
+    /// Runner-up backgrounds named `{stem}.{rank}.{ext}`, rank 1 being the next best
+    pub spare_backgrounds: PathBuf,
+    /// Backgrounds cropped and darkened for the slides, always WebP
     pub edited_backgrounds: PathBuf,
+    /// Rendered slides, named so that sorting them gives their order
     pub slides: PathBuf,
+    /// Speaker notes of the city slides by slide name, read by `scripts/generate.ts`
+    pub notes: PathBuf,
+    /// `BonaNova-Regular-Lining.ttf` and `BonaNova-Bold-Lining.ttf`
     pub fonts: PathBuf,
+    /// `home.png`, `area.png` and `population.png`, drawn next to the stats
     pub icons: PathBuf,
+    /// Maps of the voivodeships, `{name}_transparent.png`
     pub maps: PathBuf,
+    /// See [`crate::config::Config`]
+    pub config: PathBuf,
+    /// See [`crate::report::RunReport`]
+    pub report: PathBuf,
+    /// See [`crate::failures::FailureLog`]
+    pub failures: PathBuf,
+    /// Articles and images picked by hand for some cities
+    pub overrides: PathBuf,
+    /// Articles the scraper chose or rejected for every city, and why
+    pub matches: PathBuf,
+    /// Resolved image links and what was downloaded from them, kept between the two stages
+    pub manifest: PathBuf,
+    /// Infobox facts of every city, see [`crate::facts::FactLog`]
+    pub facts: PathBuf,
+    /// Authors and licences of every image, in plain text
+    pub attribution: PathBuf,
+    /// Downloads that turned out to be broken, kept there to be looked at
+    pub quarantine: PathBuf,
+    /// Images that several cities share, see [`crate::duplicates`]
+    pub duplicates: PathBuf,
+    /// Wiki API responses kept between runs
+    pub http_cache: PathBuf,
+    /// The directory everything else is in
     pub data: PathBuf,
 }
 
-pub fn workspace_root() -> AppResult<PathBuf> {
+fn workspace_root() -> AppResult<PathBuf> {
     let cwd = std::env::current_dir()?;
 
     for path in cwd.ancestors() {
@@ -26,27 +70,138 @@ pub fn workspace_root() -> AppResult<PathBuf> {
         }
     }
 
-    Err(AppError::Io(io::Error::new(
+    Err(AppError::from(io::Error::new(
         io::ErrorKind::NotFound,
         "couldn't find Cargo.lock in the working directory nor in its parents",
-    )))
+    ))
+    .with_stage(Stage::Setup)
+    .with_path(cwd))
+}
+
+/// Files making up the entry of a city, the sources are `None` until they're downloaded
+#[derive(Clone, Debug)]
+pub struct CityAssets {
+    /// Downloaded background, in whatever format it came in
+    pub background: Option<PathBuf>,
+    /// Downloaded coat of arms, the PNG rasterized from it when it came as an SVG
+    pub coa: Option<PathBuf>,
+    /// Where the edited background is, whether it was made yet or not
+    pub edited_background: PathBuf,
+    /// Where the edited coat of arms is, whether it was made yet or not
+    pub edited_coa: PathBuf,
+    /// Author and licence of the background, a JSON file the scraper writes next to it
+    pub background_credit: PathBuf,
+    /// Author and licence of the coat of arms
+    pub coa_credit: PathBuf,
+}
+
+/// Every file named `stem` with any extension, none when `directory` doesn't exist
+pub fn files_by_stem(directory: &Path, stem: &str) -> AppResult<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(AppError::from(err).with_path(directory)),
+    };
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry.path(directory)?.path();
+        if file_stem(&path).as_deref() == Some(stem) {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Finds a file named `stem` with any extension, rasterized PNGs win over the SVGs they come from
+fn find_by_stem(directory: &Path, stem: &str) -> AppResult<Option<PathBuf>> {
+    let mut found = None;
+    for path in files_by_stem(directory, stem)? {
+        let is_svg = path.extension().is_some_and(|ext| ext == "svg");
+        if found.is_none() || !is_svg {
+            found = Some(path);
+        }
+    }
+
+    Ok(found)
 }
 
 impl Paths {
+    /// Workspace layout, found by looking for `Cargo.lock` from the working directory up
     pub fn new() -> AppResult<Self> {
-        let base_dir = workspace_root()?;
-        let data = base_dir.join("data");
-        Ok(Self {
+        Ok(Self::from_data_dir(workspace_root()?.join("data")))
+    }
+
+    /// Layout rooted in an arbitrary data directory, for use outside of the workspace
+    pub fn from_data_dir(data: impl Into<PathBuf>) -> Self {
+        let data = data.into();
+        Self {
             dataset: data.join("dane.csv"),
             coas: data.join("coats-of-arms"),
             edited_coas: data.join("edited-coats-of-arms"),
             backgrounds: data.join("backgrounds"),
+            spare_backgrounds: data.join("backgrounds").join("spare"),
             edited_backgrounds: data.join("edited-backgrounds"),
             slides: data.join("slides"),
+            notes: data.join("notes.json"),
             fonts: data.join("fonts"),
             icons: data.join("icons"),
             maps: data.join("voivodeships"),
+            config: data.join("config.toml"),
+            report: data.join("report.json"),
+            failures: data.join("failures.json"),
+            overrides: data.join("overrides.toml"),
+            matches: data.join("matches.json"),
+            manifest: data.join("manifest.json"),
+            facts: data.join("facts.json"),
+            attribution: data.join("attribution.txt"),
+            quarantine: data.join("quarantine"),
+            duplicates: data.join("duplicates.json"),
+            http_cache: data.join("cache").join("http"),
             data,
+        }
+    }
+
+    /// Runner-up backgrounds of `stem` that are still around, the best first
+    pub fn spare_backgrounds_of(&self, stem: &str) -> AppResult<Vec<(usize, PathBuf)>> {
+        let directory = &self.spare_backgrounds;
+        let entries = match std::fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(AppError::from(err).with_path(directory)),
+        };
+
+        let mut spares = Vec::new();
+        for entry in entries {
+            let path = entry.path(directory)?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                continue;
+            }
+
+            let rank = file_stem(&path)
+                .and_then(|spare| spare.strip_prefix(stem)?.strip_prefix('.')?.parse().ok());
+            if let Some(rank) = rank {
+                spares.push((rank, path));
+            }
+        }
+
+        spares.sort();
+        Ok(spares)
+    }
+
+    /// Finds the files of `city`, whatever extension they were downloaded with
+    pub fn city_assets(&self, city: &City) -> AppResult<CityAssets> {
+        let stem = format_file_name(city);
+        let edited_name = format!("{stem}.webp");
+
+        Ok(CityAssets {
+            background: find_by_stem(&self.backgrounds, &stem)?,
+            coa: find_by_stem(&self.coas, &stem)?,
+            edited_background: self.edited_backgrounds.join(&edited_name),
+            edited_coa: self.edited_coas.join(edited_name),
+            background_credit: Credit::path(&self.backgrounds, &stem),
+            coa_credit: Credit::path(&self.coas, &stem),
         })
     }
 }
@@ -0,0 +1,227 @@
+#[cfg(feature = "render")]
+use crate::image_editor::process_assets;
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+use crate::slides_gen::{generate_city_slides, generate_slides};
+#[cfg(any(feature = "scrape", feature = "render"))]
+use crate::utils::{Context, Stage};
+#[cfg(feature = "scrape")]
+use crate::{
+    cache::HttpCache,
+    facts::FactLog,
+    http::Http,
+    manifest::Manifest,
+    scraper::{download_assets, get_links},
+};
+use crate::{
+    config::Config,
+    failures::FailureLog,
+    log,
+    logger::LogStyle,
+    parser::{VOIVODESHIP_COUNT, Voivodeship},
+    paths::Paths,
+    utils::{AppResult, ReturnReport},
+};
+use clap::ValueEnum;
+use std::collections::HashSet;
+
+/// Order in which article title suffixes are tried
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum SuffixStrategy {
+    /// Most specific suffixes first for names shared by several cities, plain name first otherwise
+    #[default]
+    Auto,
+    /// Always start with the plain name
+    Forward,
+    /// Always start with the most specific suffix
+    Reversed,
+}
+
+/// What [`run_pipeline`] scrapes and how
+#[derive(Clone, Debug, Default)]
+pub struct ScrapeOptions {
+    /// Only scrape, edit and render the cities with these file stems
+    pub only: Option<HashSet<String>>,
+    /// Order the article title suffixes are tried in
+    pub strategy: SuffixStrategy,
+    /// Tried after the built-in suffixes, `{voivodeship}` and `{powiat}` get replaced with the city's ones
+    pub extra_suffixes: Vec<String>,
+    /// Skip looking for links and download the ones [`Paths::manifest`] still has pending
+    pub download_only: bool,
+}
+
+/// Reports of the stages that were compiled in
+pub struct PipelineReports {
+    /// Looking up the articles and their images
+    #[cfg(feature = "scrape")]
+    pub scraper: ReturnReport,
+    /// Downloading the images that were found
+    #[cfg(feature = "scrape")]
+    pub downloader: ReturnReport,
+    /// Cropping the backgrounds
+    #[cfg(feature = "render")]
+    pub background_edit: ReturnReport,
+    /// Converting and trimming the coats of arms
+    #[cfg(feature = "render")]
+    pub coa_edit: ReturnReport,
+    /// Rendering the slides
+    #[cfg(any(feature = "export-webp", feature = "export-png"))]
+    pub slides_gen: ReturnReport,
+}
+
+impl PipelineReports {
+    /// The reports in the order the stages ran
+    pub fn to_vec(&self) -> Vec<ReturnReport> {
+        #[allow(unused_mut)]
+        let mut reports = vec![];
+        #[cfg(feature = "scrape")]
+        reports.extend([self.scraper.clone(), self.downloader.clone()]);
+        #[cfg(feature = "render")]
+        reports.extend([self.background_edit.clone(), self.coa_edit.clone()]);
+        #[cfg(any(feature = "export-webp", feature = "export-png"))]
+        reports.push(self.slides_gen.clone());
+        reports
+    }
+}
+
+/// Runs every compiled in stage, saving the failures file after each of them
+#[cfg_attr(
+    not(all(feature = "scrape", feature = "render")),
+    allow(unused_variables)
+)]
+pub async fn run_pipeline(
+    paths: &Paths,
+    config: &Config,
+    dataset: &[Voivodeship; VOIVODESHIP_COUNT],
+    options: &ScrapeOptions,
+    failures: &mut FailureLog,
+) -> AppResult<PipelineReports> {
+    #[cfg(feature = "scrape")]
+    let (scraper, downloader) = {
+        let cache = if config.http.cache {
+            HttpCache::new(&paths.http_cache, config.http.offline)
+        } else {
+            HttpCache::disabled()
+        };
+        // one client for both stages, so the rate limit holds across them
+        let http = std::sync::Arc::new(Http::new(&config.http, cache).stage(Stage::Setup)?);
+
+        let scraper = if options.download_only {
+            log!(
+                [LogStyle::Yellow],
+                "SCRAPER",
+                "Skipping the scraper, downloading the links in {:?}",
+                paths.manifest
+            );
+            ReturnReport {
+                job_name: "SCRAPER".into(),
+                ..Default::default()
+            }
+        } else {
+            let (scraper, _) = get_links(
+                paths,
+                dataset,
+                options,
+                &config.scraper,
+                http.clone(),
+                config.concurrency.scraper,
+            )
+            .await
+            .stage(Stage::Scraper)?;
+            log!([LogStyle::Purple], "JOB DONE", "{scraper}");
+            failures.record_for(&scraper, options.only.as_ref());
+            failures.save(&paths.failures)?;
+            scraper
+        };
+
+        // links that an earlier offline or interrupted run left behind get their turn too
+        let links = Manifest::load(&paths.manifest)
+            .path(&paths.manifest)
+            .stage(Stage::Downloader)?
+            .pending(&paths.data, options.only.as_ref());
+
+        let downloader = if config.http.offline {
+            // the previous failures still stand, nothing was tried again
+            log!(
+                [LogStyle::Yellow],
+                "DOWNLOADER",
+                "Offline, skipping the downloads of {} cities",
+                links.len()
+            );
+            ReturnReport {
+                job_name: "DOWNLOADER".into(),
+                ..Default::default()
+            }
+        } else {
+            let downloader = download_assets(links, paths, http, config.concurrency.downloader)
+                .await
+                .stage(Stage::Downloader)?;
+            log!([LogStyle::Purple], "JOB DONE", "{downloader}");
+            failures.record_for(&downloader, options.only.as_ref());
+            failures.save(&paths.failures)?;
+            downloader
+        };
+
+        (scraper, downloader)
+    };
+
+    // facts the scraper has just read go on the slides of this run
+    #[cfg(feature = "scrape")]
+    let dataset = &{
+        let mut dataset = dataset.clone();
+        FactLog::load(&paths.facts)
+            .path(&paths.facts)
+            .stage(Stage::Setup)?
+            .apply(&mut dataset);
+        dataset
+    };
+
+    #[cfg(not(feature = "scrape"))]
+    log!(
+        [LogStyle::Yellow],
+        "SCRAPER",
+        "Built without the `scrape` feature, using the assets already in {:?}",
+        paths.data
+    );
+
+    #[cfg(feature = "render")]
+    let (background_edit, coa_edit) = {
+        let (background_edit, coa_edit) = process_assets(
+            paths,
+            dataset,
+            options.only.as_ref(),
+            config.concurrency.image_editor,
+        )
+        .await
+        .stage(Stage::ImageEditor)?;
+        failures.record_for(&background_edit, options.only.as_ref());
+        failures.record_for(&coa_edit, options.only.as_ref());
+        failures.save(&paths.failures)?;
+
+        (background_edit, coa_edit)
+    };
+
+    #[cfg(any(feature = "export-webp", feature = "export-png"))]
+    let slides_gen = {
+        let slides_gen = match &options.only {
+            Some(only) => generate_city_slides(paths, dataset, only, &config.slides),
+            None => generate_slides(paths, dataset, &config.slides),
+        }
+        .stage(Stage::SlidesGen)?;
+        failures.record_for(&slides_gen, options.only.as_ref());
+        failures.save(&paths.failures)?;
+        slides_gen
+    };
+
+    Ok(PipelineReports {
+        #[cfg(feature = "scrape")]
+        scraper,
+        #[cfg(feature = "scrape")]
+        downloader,
+        #[cfg(feature = "render")]
+        background_edit,
+        #[cfg(feature = "render")]
+        coa_edit,
+        #[cfg(any(feature = "export-webp", feature = "export-png"))]
+        slides_gen,
+    })
+}
@@ -0,0 +1,103 @@
+use crate::logger::{self, LogLevel};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+use std::{
+    fmt::Write,
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+const TEMPLATE: &str =
+    "{prefix:15.bold.blue} [{bar:40.cyan/blue}] {pos:>4}/{len:4} {rate:>8} ETA {eta:>4}";
+const TEMPLATE_WITH_FAILURES: &str = "{prefix:15.bold.blue} [{bar:40.cyan/blue}] {pos:>4}/{len:4} {rate:>8} ETA {eta:>4}\n{msg:.red}";
+
+static BARS: LazyLock<MultiProgress> = LazyLock::new(|| {
+    let target = if logger::progress_bars_enabled() {
+        ProgressDrawTarget::stdout()
+    } else {
+        ProgressDrawTarget::hidden()
+    };
+    MultiProgress::with_draw_target(target)
+});
+
+fn style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template).unwrap().with_key(
+        "rate",
+        |state: &ProgressState, w: &mut dyn Write| {
+            let _ = write!(w, "{:.1}/s", state.per_sec());
+        },
+    )
+}
+
+/// Runs `f` with the bars temporarily cleared, so printed lines don't tear them apart
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    BARS.suspend(f)
+}
+
+/// Progress of one stage, a bar drawn on top of the shared item counter
+pub struct StageProgress {
+    counter: AtomicU32,
+    total: usize,
+    bar: ProgressBar,
+    failures: Mutex<(usize, String)>,
+}
+
+impl StageProgress {
+    pub fn new(name: &str, total: usize) -> Arc<Self> {
+        let bar = BARS.add(ProgressBar::new(total as u64));
+        bar.set_style(style(TEMPLATE));
+        bar.set_prefix(name.to_owned());
+        bar.enable_steady_tick(Duration::from_millis(200));
+
+        Arc::new(Self {
+            counter: AtomicU32::new(0),
+            total,
+            bar,
+            failures: Mutex::new((0, String::new())),
+        })
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Marks one more item as done and returns how many are done now
+    pub fn advance(&self) -> u32 {
+        let count = self.counter.fetch_add(1, Ordering::Relaxed) + 1;
+        self.bar.set_position(count as u64);
+        count
+    }
+
+    /// Records a failed item in the summary shown below the bar
+    pub fn fail(&self, item: &str, reason: impl std::fmt::Display) {
+        let mut failures = self.failures.lock().unwrap_or_else(|err| err.into_inner());
+        failures.0 += 1;
+        failures.1 = format!("{item}: {reason}");
+
+        if failures.0 == 1 {
+            self.bar.set_style(style(TEMPLATE_WITH_FAILURES));
+        }
+
+        self.bar.set_message(format!(
+            "{} failed, latest: {}",
+            failures.0,
+            failures.1.lines().next().unwrap_or_default()
+        ));
+    }
+
+    /// Level for per-item lines, the bar already shows them so they're only noise next to it
+    pub fn item_level(&self) -> LogLevel {
+        if self.bar.is_hidden() {
+            LogLevel::Info
+        } else {
+            LogLevel::Debug
+        }
+    }
+
+    /// Leaves the bar with its final state on the screen
+    pub fn finish(&self) {
+        self.bar.finish();
+    }
+}
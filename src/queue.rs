@@ -0,0 +1,35 @@
+use std::{future::Future, panic};
+use tokio::task::JoinSet;
+
+/// Runs `task` for every item, keeping up to `limit` of them in flight all the time:
+/// a new one starts as soon as any other finishes rather than when a whole batch does.
+/// Results come back in the order the tasks finished
+pub async fn run_bounded<I, F, Fut>(items: I, limit: usize, mut task: F) -> Vec<Fut::Output>
+where
+    I: IntoIterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    let mut items = items.into_iter();
+    let mut in_flight = JoinSet::new();
+    let mut results = Vec::with_capacity(items.size_hint().0);
+
+    for item in items.by_ref().take(limit.max(1)) {
+        in_flight.spawn(task(item));
+    }
+
+    while let Some(joined) = in_flight.join_next().await {
+        match joined {
+            Ok(result) => results.push(result),
+            // a panicking task is a bug, it shouldn't look like a lost item
+            Err(err) => panic::resume_unwind(err.into_panic()),
+        }
+
+        if let Some(item) = items.next() {
+            in_flight.spawn(task(item));
+        }
+    }
+
+    results
+}
@@ -0,0 +1,70 @@
+//! Summary of a whole run, saved as JSON and turned into the exit code.
+
+use crate::utils::{AppResult, ReturnReport};
+use serde::Serialize;
+use std::{path::Path, process::ExitCode};
+
+/// Exit code used when the run finished, but too many items failed
+pub const EXIT_TOO_MANY_FAILURES: u8 = 2;
+
+/// Reports of every stage and their totals
+#[derive(Serialize)]
+pub struct RunReport {
+    /// In the order the stages ran
+    pub stages: Vec<ReturnReport>,
+    /// Sum of the stage durations
+    pub total_duration_secs: f64,
+    /// Items every stage processed successfully, added up
+    pub total_ok: usize,
+    /// Items every stage failed, added up
+    pub total_err: usize,
+    /// See [`ReturnReport::bytes_downloaded`]
+    pub total_bytes_downloaded: u64,
+    /// Share of all items that failed
+    pub error_rate: f64,
+    /// See [`crate::config::ReportConfig::max_error_rate`]
+    pub max_error_rate: f64,
+    /// Whether the error rate stayed within the limit
+    pub passed: bool,
+}
+
+impl RunReport {
+    /// Adds the `stages` up and checks the error rate against `max_error_rate`
+    pub fn new(stages: Vec<ReturnReport>, max_error_rate: f64) -> Self {
+        let total = stages
+            .iter()
+            .cloned()
+            .reduce(|acc, report| acc + report)
+            .unwrap_or(ReturnReport {
+                job_name: "TOTAL".into(),
+                ..Default::default()
+            });
+        let error_rate = total.error_rate();
+
+        Self {
+            stages,
+            total_duration_secs: total.duration.as_secs_f64(),
+            total_ok: total.amount_ok,
+            total_err: total.amount_err,
+            total_bytes_downloaded: total.bytes_downloaded,
+            error_rate,
+            max_error_rate,
+            passed: error_rate <= max_error_rate,
+        }
+    }
+
+    /// Writes the report as JSON
+    pub fn save(&self, path: &Path) -> AppResult<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Success, or [`EXIT_TOO_MANY_FAILURES`] when the run didn't pass
+    pub fn exit_code(&self) -> ExitCode {
+        if self.passed {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::from(EXIT_TOO_MANY_FAILURES)
+        }
+    }
+}
@@ -1,43 +1,129 @@
 use crate::{
+    candidates::{coat_of_arms, rank, same_file},
+    config::{ScraperConfig, Source},
+    credits::Credit,
+    facts::{self, FactLog, Facts, Summary},
+    http::Http,
     log,
-    logger::{LogStyle, log_msg},
-    parser::{VOIVODESHIP_COUNT, Voivodeship},
+    logger::{LogLevel, LogStyle, log_msg},
+    manifest::{self, AssetEntry, Download, Manifest},
+    overrides::{ImageSource, Override, Overrides},
+    parser::{City, VOIVODESHIP_COUNT, Voivodeship},
     paths::Paths,
+    pipeline::{ScrapeOptions, SuffixStrategy},
+    progress::StageProgress,
+    queue::run_bounded,
     utils::{
-        AppError, AppResult, ReturnReport, ensure_exists, file_stem, format_file_name,
-        format_file_name_parts,
+        AppError, AppResult, AssetKind, Context, Failure, OverrideUse, Reason, ReturnReport, Stage,
+        ensure_exists, file_stem, format_file_name,
     },
+    validate::{
+        check_whole, is_temp_file, quarantine, quarantine_bytes, validate_blocking, write_atomic,
+    },
+    verify::{MatchLog, MatchRecord, Verdict, teryt_digits},
+    wiki::{FileInfo, PageLookup, WikiApi, WikidataItem},
 };
-use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
     fs,
-    path::Path,
-    sync::{
-        Arc,
-        atomic::{AtomicU32, Ordering},
-    },
+    path::{Path, PathBuf},
+    sync::Arc,
     time,
 };
-use tokio::task::JoinSet;
 
-const CONCURRENT_DOWNLOADS: usize = 10;
-const USER_AGENT: &str = "radio/video";
+/// TERC code of a municipality, what the dataset calls an identifier
+const TERYT_PROPERTY: &str = "P1653";
+
+/// Width backgrounds are downloaded at, what `edit_background_image` scales them to
+pub const BACKGROUND_WIDTH: u32 = 1920;
+
+/// An image to download and the credit to keep next to it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImageLink {
+    pub source: ImageSource,
+    pub credit: Credit,
+}
+
+impl ImageLink {
+    /// Overrides are put there by hand, all that's known is where they come from
+    fn overridden(source: &ImageSource) -> Self {
+        let origin = match source {
+            ImageSource::Url(url) => url.clone(),
+            ImageSource::File(path) => path.display().to_string(),
+        };
+
+        Self {
+            source: source.clone(),
+            credit: Credit {
+                source: origin,
+                found_on: Some("override".into()),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// A file the scraper found on the source named `found_on`
+    fn scraped(info: FileInfo, found_on: &str) -> Self {
+        Self {
+            source: ImageSource::Url(info.url),
+            credit: Credit {
+                found_on: Some(found_on.to_owned()),
+                ..info.credit
+            },
+        }
+    }
+}
 
+/// Where to get a city's images from, scraped ones are direct URLs of the uploads,
+/// thumbnails for backgrounds and originals for coats of arms.
+/// Only the kinds that were asked for and found are there
+#[derive(Default)]
 pub struct Links {
-    pub coa_link: String,
-    pub bg_link: String,
+    pub coa: Option<ImageLink>,
+    /// The best first and the runners-up kept as spares, empty when there's none
+    pub bg: Vec<ImageLink>,
 }
 
-fn log_try_page(positive: bool, prefix: &str, reason: &str, city_link: String) {
+impl Links {
+    fn has(&self, kind: AssetKind) -> bool {
+        match kind {
+            AssetKind::Coa => self.coa.is_some(),
+            AssetKind::Background => !self.bg.is_empty(),
+        }
+    }
+
+    /// Kinds among `wanted` that aren't there
+    pub fn missing(&self, wanted: &[AssetKind]) -> Vec<AssetKind> {
+        wanted
+            .iter()
+            .copied()
+            .filter(|&kind| !self.has(kind))
+            .collect()
+    }
+
+    /// Takes the kinds that aren't there yet from `other`, returning which ones were taken
+    fn merge(&mut self, other: Links) -> Vec<AssetKind> {
+        let mut taken = Vec::new();
+        if self.coa.is_none() && other.coa.is_some() {
+            self.coa = other.coa;
+            taken.push(AssetKind::Coa);
+        }
+        if self.bg.is_empty() && !other.bg.is_empty() {
+            self.bg = other.bg;
+            taken.push(AssetKind::Background);
+        }
+        taken
+    }
+}
+
+fn log_try_page(level: LogLevel, positive: bool, prefix: &str, reason: &str, city_link: String) {
     let color = if positive {
         LogStyle::Green
     } else {
         LogStyle::Yellow
     };
-    log!(
-        [color],
-        prefix,
+    let message = format!(
         "{}{reason:20}{} {}{color}\u{2022}{} {}.../wiki/{city_link}{}",
         LogStyle::Italic,
         LogStyle::Clear,
@@ -45,112 +131,563 @@ fn log_try_page(positive: bool, prefix: &str, reason: &str, city_link: String) {
         LogStyle::Clear,
         LogStyle::Cyan,
         LogStyle::Clear,
-    )
+    );
+
+    log_msg(level, [color], prefix, message);
+}
+
+fn log_miss(reason: &str, city_link: String) {
+    // misses are expected for most of the suffixes, only show them when asked to
+    log_try_page(LogLevel::Debug, false, "NO MATCH", reason, city_link);
+}
+
+fn is_allowed(info: &FileInfo, licences: &[String]) -> bool {
+    let allowed = info.credit.is_allowed(licences);
+    if !allowed {
+        log!(
+            Debug: [LogStyle::Yellow],
+            "LICENCE",
+            "{}: {} isn't allowed",
+            info.file,
+            info.credit.licence.as_deref().unwrap_or("no licence")
+        );
+    }
+    allowed
+}
+
+/// Ranks the `files` of a source together with the `preferred` one that Wikidata or the infobox
+/// picks, keeping the best one and [`ScraperConfig::spare_backgrounds`] runners-up
+async fn background_links(
+    lookup: &Lookup,
+    source: &SourceApi,
+    mut files: Vec<FileInfo>,
+    preferred: Option<&str>,
+    coa: Option<&str>,
+) -> AppResult<Vec<ImageLink>> {
+    let width = Some(BACKGROUND_WIDTH);
+
+    // Wikidata photos come from Commons and don't have to be among the files
+    if let Some(preferred) = preferred
+        && !files.iter().any(|file| same_file(&file.file, preferred))
+        && let Some(info) = source.api.file_info(preferred, width).await?
+    {
+        files.insert(0, info);
+    }
+
+    let ranked = rank(files, preferred, coa);
+    log!(
+        Debug: [LogStyle::Blue],
+        "CANDIDATES",
+        "{}: {}",
+        source.config.name(),
+        ranked
+            .iter()
+            .map(|(file, score)| format!("{} ({score})", file.file))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    Ok(ranked
+        .into_iter()
+        .map(|(file, _)| file)
+        .filter(|file| is_allowed(file, &lookup.licences))
+        .take(1 + lookup.spare_backgrounds)
+        .map(|file| ImageLink::scraped(file, source.config.name()))
+        .collect())
 }
 
-async fn try_page<const N: usize, const M: usize>(
-    city_data: (String, String),
-    suffixes: [String; N],
-    regexes: Arc<[(Regex, Regex); M]>,
-    replacement_regex: Arc<Regex>,
-    client: reqwest::Client,
-    counter: Arc<AtomicU32>,
-    total: usize,
-) -> Option<(String, Links)> {
-    let city_name = city_data.0;
-    let city_identifier = city_data.1;
-
-    for (coa_regex, bg_regex) in regexes.iter() {
-        for suffix in &suffixes {
-            let city_link = format!("{city_name}{suffix}").replace(' ', "_");
-            let url = format!("https://pl.wikipedia.org/wiki/{city_link}");
-
-            let response = client
-                .get(url)
-                .send()
+/// Resolves the `needed` kinds, telling why the ones that weren't found can't be used.
+/// Backgrounds are picked from the `files` of the source and the `bg` file
+async fn file_links(
+    lookup: &Lookup,
+    source: &SourceApi,
+    files: Vec<FileInfo>,
+    coa: Option<&str>,
+    bg: Option<&str>,
+    needed: &[AssetKind],
+) -> AppResult<(Links, Vec<&'static str>)> {
+    let mut links = Links::default();
+    let mut reasons = Vec::new();
+
+    if needed.contains(&AssetKind::Coa) {
+        match coa {
+            Some(file) => match source.api.file_info(file, None).await? {
+                Some(info) if is_allowed(&info, &lookup.licences) => {
+                    links.coa = Some(ImageLink::scraped(info, source.config.name()));
+                }
+                Some(_) => reasons.push("COA licence not allowed"),
+                None => reasons.push("no COA file"),
+            },
+            None => reasons.push("no COA"),
+        }
+    }
+
+    if needed.contains(&AssetKind::Background) {
+        links.bg = background_links(lookup, source, files, bg, coa).await?;
+        if links.bg.is_empty() {
+            reasons.push("no usable background");
+        }
+    }
+
+    Ok((links, reasons))
+}
+
+/// Wikidata item of the municipality, TERYT `226401 1` is stored there as `2264011`
+async fn wikidata_item(
+    wikidata: &WikiApi,
+    identifier: &str,
+    sites: &[&str],
+) -> AppResult<Option<WikidataItem>> {
+    let Some(id) = wikidata
+        .find_item(TERYT_PROPERTY, &teryt_digits(identifier))
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    wikidata.item(&id, sites).await
+}
+
+/// A configured source with the client of its API
+struct SourceApi {
+    config: Source,
+    api: WikiApi,
+}
+
+/// What every city is looked up with
+struct Lookup {
+    /// See [`ScraperConfig::sources`]
+    sources: Vec<SourceApi>,
+    wikidata: WikiApi,
+    /// See [`ScraperConfig::min_score`]
+    min_score: i32,
+    /// See [`ScraperConfig::licences`]
+    licences: Vec<String>,
+    /// See [`ScraperConfig::spare_backgrounds`]
+    spare_backgrounds: usize,
+    /// See [`ScraperConfig::summary_chars`]
+    summary_chars: usize,
+}
+
+/// What is still needed of a city
+#[derive(Clone)]
+struct Wanted {
+    assets: Vec<AssetKind>,
+    /// Facts and the summary are read from the first article that passes verification
+    facts: bool,
+}
+
+/// What was learnt about a city on the way, kept whether or not its images were found
+#[derive(Default)]
+struct Findings {
+    record: MatchRecord,
+    facts: Option<Facts>,
+}
+
+/// Looks for the `wanted` kinds and facts source by source, keeping whatever is found on the way.
+/// Fails only when nothing was found at all
+async fn try_page(
+    city: City,
+    suffixes: Vec<String>,
+    pinned: Override,
+    wanted: Wanted,
+    lookup: Arc<Lookup>,
+    progress: Arc<StageProgress>,
+    findings: &mut Findings,
+) -> Result<(String, Links), Failure> {
+    let Lookup {
+        sources,
+        wikidata,
+        min_score,
+        summary_chars,
+        ..
+    } = &*lookup;
+    let city_name = &city.name;
+    let file_name = format_file_name(&city);
+    let total = progress.total();
+    let fail = |err: AppError| {
+        let err = err.with_stage(Stage::Scraper).with_city(&file_name);
+        log!(Error: [LogStyle::Bold, LogStyle::Red], "CRITICAL ERROR", "{err}");
+        progress.advance();
+        progress.fail(&file_name, &err.kind);
+        Failure::new(&file_name, &err)
+    };
+    let hit = |reason: &str, city_link: String| {
+        log_try_page(
+            progress.item_level(),
+            true,
+            &format!("HIT{:>12}", format!("{}/{total}", progress.advance())),
+            reason,
+            city_link,
+        );
+    };
+
+    let mut found = Links::default();
+    if wanted.assets.contains(&AssetKind::Coa) {
+        found.coa = pinned.coa.as_ref().map(ImageLink::overridden);
+    }
+    if wanted.assets.contains(&AssetKind::Background) {
+        found.bg = pinned
+            .background
+            .iter()
+            .map(ImageLink::overridden)
+            .collect();
+    }
+
+    let overridden: Vec<_> = wanted
+        .assets
+        .iter()
+        .copied()
+        .filter(|&kind| found.has(kind))
+        .collect();
+    if !overridden.is_empty() {
+        findings
+            .record
+            .accept(city_name, "override", None, &overridden);
+    }
+    // facts are only in the articles, overrides can't skip them
+    if found.missing(&wanted.assets).is_empty() && !wanted.facts {
+        hit("OVERRIDE", city_name.replace(' ', "_"));
+        return Ok((file_name, found));
+    }
+
+    // a pinned article is there because the item or the guesses lead somewhere wrong
+    let sites: Vec<&str> = sources
+        .iter()
+        .filter_map(|source| match &source.config {
+            Source::Wikipedia { site, .. } => Some(site.as_str()),
+            Source::Commons { .. } => None,
+        })
+        .collect();
+    let item = match pinned.article {
+        Some(_) => None,
+        None => wikidata_item(wikidata, &city.identifier, &sites)
+            .await
+            .map_err(fail)?,
+    };
+    if let Some(item) = &item {
+        log!(
+            Debug: [LogStyle::Blue],
+            "WIKIDATA",
+            "{city_name} is {}: {item:?}",
+            item.id
+        );
+    }
+    let (item_coa, item_image) = match &item {
+        Some(item) => (item.coat_of_arms.clone(), item.image.clone()),
+        None => (None, None),
+    };
+
+    'sources: for source in sources {
+        let name = source.config.name();
+
+        let (coa_params, photo_params, fact_params, trusted, titles) = match &source.config {
+            Source::Commons { .. } => {
+                let needed = found.missing(&wanted.assets);
+                let Some(category) = item
+                    .as_ref()
+                    .and_then(|item| item.commons_category.as_ref())
+                    .filter(|_| !needed.is_empty())
+                else {
+                    continue;
+                };
+                let city_link = format!("Category:{category}").replace(' ', "_");
+
+                let files = source
+                    .api
+                    .category_files(category, Some(BACKGROUND_WIDTH))
+                    .await
+                    .map_err(fail)?;
+                let coa = item_coa.clone().or(coat_of_arms(&files).map(String::from));
+                let (links, reasons) = file_links(
+                    &lookup,
+                    source,
+                    files,
+                    coa.as_deref(),
+                    item_image.as_deref(),
+                    &needed,
+                )
                 .await
-                .inspect_err(|err| log!([LogStyle::Bold, LogStyle::Red], "CRITICAL ERROR", "{err}"))
-                .ok()?;
-
-            if let Err(error) = response.error_for_status_ref() {
-                log_try_page(
-                    false,
-                    "FAIL",
-                    &format!(
-                        "{} {}",
-                        error.status().unwrap().as_u16(),
-                        error.status().unwrap().canonical_reason().unwrap()
-                    ),
-                    city_link,
-                );
+                .map_err(fail)?;
+
+                let taken = found.merge(links);
+                if taken.is_empty() {
+                    let reason = reasons.join(", ");
+                    log_miss(&reason, city_link);
+                    findings.record.reject(category, reason, None);
+                    continue;
+                }
+
+                findings.record.accept(category, name, None, &taken);
+                if found.missing(&wanted.assets).is_empty() {
+                    hit("COMMONS OK", city_link);
+                    return Ok((file_name, found));
+                }
+                log_miss(&reasons.join(", "), city_link);
                 continue;
             }
+            Source::Wikipedia {
+                site,
+                guess_titles,
+                coa_params,
+                photo_params,
+                fact_params,
+                ..
+            } => {
+                // pages linked from the item or pinned by hand are known to be about the city
+                let trusted = pinned
+                    .article
+                    .clone()
+                    .filter(|_| *guess_titles)
+                    .or_else(|| item.as_ref()?.articles.get(site).cloned());
+                let mut titles: Vec<String> = trusted.iter().cloned().collect();
+                if *guess_titles && pinned.article.is_none() {
+                    titles.extend(suffixes.iter().map(|suffix| {
+                        // suffixes are written the way they appear in links,
+                        // the API wants titles with spaces
+                        format!("{city_name}{suffix}").replace('_', " ")
+                    }));
+                    titles.dedup();
+                }
+                (coa_params, photo_params, fact_params, trusted, titles)
+            }
+        };
 
-            let text = response
-                .text()
-                .await
-                .inspect_err(|err| log!([LogStyle::Bold, LogStyle::Red], "CRITICAL ERROR", "{err}"))
-                .ok()?;
+        for title in titles {
+            let city_link = title.replace(' ', "_");
 
-            let Some(coa_captures) = coa_regex.captures(&text) else {
-                log_try_page(false, "NO MATCH", "no COA", city_link);
-                continue;
+            let article = match source.api.page(&title).await.map_err(fail)? {
+                PageLookup::Found(article) => article,
+                PageLookup::Missing => {
+                    log_try_page(LogLevel::Debug, false, "FAIL", "no such page", city_link);
+                    findings.record.reject(&title, "no such page", None);
+                    continue;
+                }
+                PageLookup::Disambiguation => {
+                    log_miss("disambiguation", city_link);
+                    findings.record.reject(&title, "disambiguation", None);
+                    continue;
+                }
             };
 
-            let Some(bg_captures) = bg_regex.captures(&text) else {
-                log_try_page(false, "NO MATCH", "no background", city_link);
-                continue;
-            };
+            if let Some(from) = &article.redirected_from {
+                log!(
+                    Debug: [LogStyle::Blue],
+                    "REDIRECT",
+                    "{from} -> {}",
+                    article.title
+                );
+            }
 
-            let bg_cap = bg_captures.get(1).unwrap().as_str();
-            let coa_cap = coa_captures.get(1).unwrap().as_str();
+            let infobox = source.api.infobox(&article.title).await.map_err(fail)?;
+            let verdict = Verdict::new(&city, &infobox);
+            let score = Some(verdict.score);
 
-            if coa_cap == bg_cap {
-                log_try_page(false, "NO MATCH", "images repeat", city_link);
+            if trusted.as_ref() != Some(&title) && verdict.score < *min_score {
+                let reason = format!("score {} < {min_score}", verdict.score);
+                log_miss(&reason, city_link);
+                log!(
+                    Debug: [LogStyle::Yellow],
+                    "REJECTED",
+                    "{}: {}",
+                    article.title,
+                    verdict.checks.join(", ")
+                );
+                findings
+                    .record
+                    .reject(&article.title, verdict.checks.join(", "), score);
                 continue;
-            };
+            }
 
-            let coa_link = String::from("https:") + coa_cap;
-            let bg_link = String::from("https:")
-                + &replacement_regex.replace(&bg_cap.replace("/thumb", ""), "");
+            if wanted.facts && findings.facts.is_none() {
+                let mut found_facts = facts::extract(&infobox, fact_params, name, &article.title);
+                if *summary_chars > 0 {
+                    let lead = source.api.lead(&article.title).await.map_err(fail)?;
+                    found_facts.summary = lead
+                        .and_then(|lead| facts::summarize(&lead, *summary_chars))
+                        .map(|text| Summary {
+                            text,
+                            site: name.to_owned(),
+                            page: article.title.clone(),
+                        });
+                    found_facts.no_lead = found_facts.summary.is_none();
+                }
+                findings.facts = Some(found_facts);
+            }
 
-            log_try_page(
-                true,
-                &format!(
-                    "HIT{:>12}",
-                    format!("{}/{total}", counter.fetch_add(1, Ordering::Relaxed) + 1)
-                ),
-                "COA OK, BG OK",
-                city_link,
-            );
+            // whatever Wikidata knows wins over the infobox
+            let coa = item_coa.clone().or(infobox.file(coa_params));
+            let bg = item_image
+                .clone()
+                .or(infobox.file(photo_params))
+                .or(article.page_image);
+
+            let needed = found.missing(&wanted.assets);
+            let files = if needed.is_empty() {
+                vec![]
+            } else {
+                source
+                    .api
+                    .page_images(&article.title, Some(BACKGROUND_WIDTH))
+                    .await
+                    .map_err(fail)?
+            };
+            let (links, reasons) = file_links(
+                &lookup,
+                source,
+                files,
+                coa.as_deref(),
+                bg.as_deref(),
+                &needed,
+            )
+            .await
+            .map_err(fail)?;
 
-            return Some((
-                format_file_name_parts(&city_identifier, &city_name),
-                Links { coa_link, bg_link },
-            ));
+            let taken = found.merge(links);
+            if found.missing(&wanted.assets).is_empty() {
+                hit(&format!("{name} OK"), city_link);
+                findings
+                    .record
+                    .accept(&article.title, name, Some(verdict), &taken);
+                return Ok((file_name, found));
+            }
+
+            let reason = reasons.join(", ");
+            log_miss(&reason, city_link);
+            if taken.is_empty() {
+                findings.record.reject(&article.title, reason, score);
+            } else {
+                // this is the city's article, the other titles won't have what it lacks
+                findings
+                    .record
+                    .accept(&article.title, name, Some(verdict), &taken);
+                continue 'sources;
+            }
         }
     }
 
+    if wanted.assets.is_empty() {
+        // a city without facts is no failure, they're only nice to have
+        progress.advance();
+        log_miss("no article for the facts", city_name.replace(' ', "_"));
+        return Ok((file_name, found));
+    }
+
+    let missing = found.missing(&wanted.assets);
+    findings.record.missing(&missing);
+    if missing.len() < wanted.assets.len() {
+        let missing: Vec<_> = missing.iter().map(ToString::to_string).collect();
+        hit(
+            &format!("PARTIAL, NO {}", missing.join(", ").to_uppercase()),
+            city_name.replace(' ', "_"),
+        );
+        return Ok((file_name, found));
+    }
+
+    let err = AppError::from(Reason::NoMatchingPage)
+        .with_stage(Stage::Scraper)
+        .with_city(&file_name);
+    let count = progress.advance();
+    progress.fail(&file_name, &err.kind);
     log!(
-        [LogStyle::Red],
-        &format!(
-            "ERR{:>12}",
-            format!("{}/{total}", counter.fetch_add(1, Ordering::Relaxed) + 1)
-        ),
+        Warn: [LogStyle::Red],
+        &format!("ERR{:>12}", format!("{count}/{total}")),
         "No image found for city {city_name}.",
     );
 
-    None
+    findings.record.source = "none".into();
+    Err(Failure::new(file_name, &err))
+}
+
+/// Stems of the images in `folder` that are whole. Broken ones are moved to [`Paths::quarantine`]
+/// and leftovers of interrupted downloads removed, so that they're scraped again. One of another
+/// size than the manifest's `sizes` was replaced or cropped by hand and is kept
+fn intact_stems(
+    paths: &Paths,
+    folder: &Path,
+    sizes: &HashMap<PathBuf, Vec<u64>>,
+) -> AppResult<HashSet<String>> {
+    let mut stems = HashSet::new();
+    for entry in fs::read_dir(folder).path(folder)? {
+        let path = entry?.path();
+        let Some(stem) = file_stem(&path) else {
+            continue;
+        };
+        if path.is_dir() || path.extension().is_some_and(|ext| ext == "json") {
+            continue;
+        }
+        if is_temp_file(&path) {
+            fs::remove_file(&path).path(&path)?;
+            continue;
+        }
+        // `.gitkeep`, `.DS_Store` and the like
+        if stem.starts_with('.') {
+            continue;
+        }
+
+        let bytes = fs::read(&path).path(&path)?;
+        if let Err(reason) = check_whole(&bytes) {
+            let kept = quarantine(&paths.quarantine, &path)?;
+            log!(
+                Warn: [LogStyle::Yellow],
+                "QUARANTINE",
+                "{path:?}: {reason}, moved to {kept:?}"
+            );
+            continue;
+        }
+
+        if let Some(sizes) = sizes.get(&path)
+            && !sizes.contains(&(bytes.len() as u64))
+        {
+            log!(
+                Debug: [LogStyle::Blue],
+                "EDITED",
+                "{path:?} isn't the downloaded file anymore, keeping it"
+            );
+        }
+        stems.insert(stem);
+    }
+    Ok(stems)
+}
+
+/// Whether the file saved for an asset is what `pinned` asks for. A source override has to be
+/// where the file came from, a file override has to still have the same contents, and a pinned
+/// article has to be the page the file was found on
+fn pinned_saved(
+    paths: &Paths,
+    entry: Option<&AssetEntry>,
+    source: Option<&ImageSource>,
+    article: Option<&str>,
+) -> bool {
+    let Some(entry) = entry else {
+        return false;
+    };
+
+    match (source, &entry.download) {
+        (Some(_), None) => false,
+        (Some(ImageSource::Url(url)), Some(download)) => download.from == *url,
+        (Some(ImageSource::File(path)), Some(download)) => {
+            let path = paths.data.join(path);
+            download.from == path.display().to_string()
+                && fs::read(&path).is_ok_and(|bytes| manifest::sha256(&bytes) == download.sha256)
+        }
+        (None, _) => article.is_none_or(|article| entry.page.as_deref() == Some(article)),
+    }
 }
 
 pub async fn get_links(
     paths: &Paths,
     dataset: &[Voivodeship; VOIVODESHIP_COUNT],
+    options: &ScrapeOptions,
+    config: &ScraperConfig,
+    http: Arc<Http>,
+    concurrency: usize,
 ) -> AppResult<(ReturnReport, Vec<(String, Links)>)> {
     let start_time = time::Instant::now();
 
     ensure_exists(&paths.coas)?;
     ensure_exists(&paths.backgrounds)?;
+    ensure_exists(&paths.spare_backgrounds)?;
 
     log!(
         [LogStyle::Blue],
@@ -158,28 +695,84 @@ pub async fn get_links(
         "Checking for existing entries..."
     );
 
-    let mut backgrounds_stems = HashSet::new();
-    for entry in fs::read_dir(&paths.backgrounds)? {
-        if let Some(stem) = file_stem(&entry?.path()) {
-            backgrounds_stems.insert(stem);
-        }
-    }
+    let mut manifest = Manifest::load(&paths.manifest).path(&paths.manifest)?;
+    let sizes = manifest.sizes(&paths.data);
+    let backgrounds_stems = intact_stems(paths, &paths.backgrounds, &sizes)?;
+    let coa_stems = intact_stems(paths, &paths.coas, &sizes)?;
+    let mut facts = FactLog::load(&paths.facts).path(&paths.facts)?;
 
-    let mut coa_stems = HashSet::new();
-    for entry in fs::read_dir(&paths.coas)? {
-        if let Some(stem) = file_stem(&entry?.path()) {
-            coa_stems.insert(stem);
-        }
+    let overrides = Overrides::load(&paths.overrides).path(&paths.overrides)?;
+    if !overrides.is_empty() {
+        log!(
+            [LogStyle::Blue],
+            "SCRAPER",
+            "Loaded {} overrides from {:?}",
+            overrides.len(),
+            paths.overrides
+        );
     }
 
     let mut cities = Vec::new();
+    let mut used_overrides = Vec::new();
     for voivodeship in dataset.iter() {
         for city in voivodeship.content.iter() {
             let filename = format_file_name(city);
-            let has_bg = backgrounds_stems.contains(&filename);
-            let has_coa = coa_stems.contains(&filename);
-            if !(has_bg && has_coa) {
-                cities.push(city);
+            let pinned = overrides.get(city);
+            let selected = options
+                .only
+                .as_ref()
+                .is_none_or(|only| only.contains(&filename));
+            if !selected {
+                continue;
+            }
+
+            // overridden parts are scraped again once the override no longer matches what
+            // was saved, a pinned article can change both
+            let saved = manifest.cities.get(&filename);
+            let stale = |kind: AssetKind| {
+                pinned.is_some_and(|pinned| {
+                    let source = match kind {
+                        AssetKind::Coa => pinned.coa.as_ref(),
+                        AssetKind::Background => pinned.background.as_ref(),
+                    };
+                    (source.is_some() || pinned.article.is_some())
+                        && !pinned_saved(
+                            paths,
+                            saved.and_then(|city| city.asset(kind)),
+                            source,
+                            pinned.article.as_deref(),
+                        )
+                })
+            };
+            let mut assets = Vec::new();
+            if !coa_stems.contains(&filename) || stale(AssetKind::Coa) {
+                assets.push(AssetKind::Coa);
+            }
+            if !backgrounds_stems.contains(&filename) || stale(AssetKind::Background) {
+                assets.push(AssetKind::Background);
+            }
+
+            // summaries are asked for while they're on, unless the article has no lead
+            let known = facts.cities.get(&filename);
+            let repinned = pinned.is_some_and(|pinned| pinned.article.is_some())
+                && assets.iter().any(|&kind| stale(kind));
+            let wanted = Wanted {
+                facts: known.is_none_or(|known| {
+                    config.summary_chars > 0 && known.summary.is_none() && !known.no_lead
+                }) || repinned,
+                assets,
+            };
+
+            if !wanted.assets.is_empty()
+                && let Some(pinned) = pinned
+            {
+                used_overrides.push(OverrideUse {
+                    item: filename,
+                    fields: pinned.fields(),
+                });
+            }
+            if !wanted.assets.is_empty() || wanted.facts {
+                cities.push((city, pinned.cloned().unwrap_or_default(), wanted));
             }
         }
     }
@@ -198,8 +791,9 @@ pub async fn get_links(
     );
 
     let repeating_names: HashSet<&str> = {
+        // a name is shared whether or not the other cities still need scraping
         let mut name_counts: HashMap<&str, usize> = HashMap::new();
-        for &city in &cities {
+        for city in dataset.iter().flat_map(|voivodeship| &voivodeship.content) {
             *name_counts.entry(&city.name).or_default() += 1;
         }
 
@@ -209,212 +803,449 @@ pub async fn get_links(
             .collect()
     };
 
-    let replacement_regex = Arc::new(Regex::new(r"/[^/]*?$").unwrap());
-    let regexes_list = Arc::new([
-        (
-            Regex::new(r#"<img .*?alt="Herb" .*?src="(.+?)".*?>"#).unwrap(),
-            Regex::new(r#"(?s)<tr class="grafika iboxs.*?<img .*?src="(.+?)".*?>"#).unwrap(),
-        ),
-        (
-            Regex::new(r#"<img .*?alt="Herb" .*?src="(.+?)".*?>"#).unwrap(),
-            Regex::new(r#"(?s).*<figure .*?typeof="mw:File/Thumb".*?<img .*?src="(.+?)".*?>"#)
-                .unwrap(),
-        ),
-        (
-            Regex::new(r#"<img .*?src="(.+?COA.+?)".*?>"#).unwrap(),
-            Regex::new(r#"(?i)<img .*?alt="Ilustracja" .*?src="(.+?)".*?>"#).unwrap(),
-        ),
-    ]);
-
-    let client = reqwest::Client::builder().user_agent(USER_AGENT).build()?;
-    let download_counter = Arc::new(AtomicU32::new(0));
-    let total_downloads = cities.len();
+    let lookup = Arc::new(Lookup {
+        sources: config
+            .sources
+            .iter()
+            .map(|source| SourceApi {
+                api: WikiApi::new(
+                    http.clone(),
+                    match source {
+                        Source::Wikipedia { api_url, .. } | Source::Commons { api_url } => api_url,
+                    },
+                ),
+                config: source.clone(),
+            })
+            .collect(),
+        wikidata: WikiApi::new(http, &config.wikidata_url),
+        min_score: config.min_score,
+        licences: config.licences.clone(),
+        spare_backgrounds: config.spare_backgrounds,
+        summary_chars: config.summary_chars,
+    });
+    let total_assets: usize = cities
+        .iter()
+        .map(|(_, _, wanted)| wanted.assets.len())
+        .sum();
+    let progress = StageProgress::new("SCRAPER", cities.len());
 
-    let mut links: Vec<Option<(String, Links)>> = vec![];
+    let links = run_bounded(cities, concurrency, |(city, pinned, wanted)| {
+        let reverse = match options.strategy {
+            SuffixStrategy::Auto => repeating_names.contains(&*city.name),
+            SuffixStrategy::Forward => false,
+            SuffixStrategy::Reversed => true,
+        };
+        if reverse {
+            log!(
+                Debug: [LogStyle::Blue],
+                "SUFFIXES",
+                "Reversing suffixes for '{}'",
+                city.name
+            );
+        }
 
-    for chunk in cities.chunks(CONCURRENT_DOWNLOADS) {
-        let mut join_set = JoinSet::new();
-        for &city in chunk {
-            let repeating = repeating_names.contains(&*city.name);
-            if repeating {
-                log!(
-                    [LogStyle::Blue],
-                    "REPEATING",
-                    "Reversing suffixes for '{}'",
-                    city.name
-                );
-            }
+        let voivodeship_suffix = format!("_(województwo_{})", city.voivodeship);
+        let powiat_suffix = format!("_(powiat_{})", city.powiat);
 
-            let voivodeship_suffix = format!("_(województwo_{})", city.voivodeship);
-            let powiat_suffix = format!("_(powiat_{})", city.powiat);
+        let mut suffixes = vec![
+            "".into(),
+            "_(miasto)".into(),
+            voivodeship_suffix,
+            powiat_suffix,
+        ];
 
-            let mut suffixes = [
-                "".into(),
-                "_(miasto)".into(),
-                voivodeship_suffix,
-                powiat_suffix,
-            ];
+        if reverse {
+            suffixes.reverse();
+        }
 
-            if repeating {
-                suffixes.reverse();
-            }
+        suffixes.extend(options.extra_suffixes.iter().map(|suffix| {
+            suffix
+                .replace("{voivodeship}", &city.voivodeship)
+                .replace("{powiat}", &city.powiat)
+                .replace(' ', "_")
+        }));
 
-            join_set.spawn(try_page(
-                (city.name.clone(), city.identifier.clone()),
+        let city = city.clone();
+        let lookup = lookup.clone();
+        let progress = progress.clone();
+        async move {
+            let file_name = format_file_name(&city);
+            let mut findings = Findings::default();
+            let result = try_page(
+                city,
                 suffixes,
-                regexes_list.clone(),
-                replacement_regex.clone(),
-                client.clone(),
-                download_counter.clone(),
-                total_downloads,
-            ));
+                pinned,
+                wanted.clone(),
+                lookup,
+                progress,
+                &mut findings,
+            )
+            .await;
+            (file_name, findings, wanted, result)
         }
+    })
+    .await;
 
-        let mut res = join_set.join_all().await;
-        links.append(&mut res);
-    }
+    progress.finish();
 
-    let collected_links: Vec<_> = links.into_iter().flatten().collect();
+    let mut matches = MatchLog::load(&paths.matches).path(&paths.matches)?;
+    let mut collected_links = Vec::with_capacity(links.len());
+    let mut failures = Vec::new();
+    let mut amount_ok = 0;
+    for (
+        file_name,
+        Findings {
+            record,
+            facts: found_facts,
+        },
+        wanted,
+        result,
+    ) in links
+    {
+        if let Ok((_, found)) = &result {
+            manifest.record_links(&file_name, found, &record);
+        }
+        // a city with no article found keeps being looked for, one without facts in it doesn't
+        match found_facts {
+            Some(mut found_facts) => {
+                // summaries aren't fetched while they're off, the ones already scraped stay
+                if let Some(known) = facts.cities.get(&file_name)
+                    && found_facts.summary.is_none()
+                    && !found_facts.no_lead
+                {
+                    found_facts.summary = known.summary.clone();
+                }
+                facts.cities.insert(file_name.clone(), found_facts);
+            }
+            // what an earlier run found stays when no article passed verification this time,
+            // but the lead isn't asked for again
+            None if wanted.facts && result.is_ok() => {
+                let known = facts.cities.entry(file_name.clone()).or_default();
+                known.no_lead |= config.summary_chars > 0 && known.summary.is_none();
+            }
+            None => {}
+        }
+        // cities that only wanted facts would lose what's known about their images
+        if !wanted.assets.is_empty() {
+            matches.cities.insert(file_name, record);
+        }
+        match result {
+            Ok((file_name, found)) => {
+                for kind in found.missing(&wanted.assets) {
+                    let err = AppError::from(Reason::NoAsset(kind))
+                        .with_stage(Stage::Scraper)
+                        .with_city(&file_name);
+                    failures.push(Failure::new(&file_name, &err).with_asset(kind));
+                }
+                amount_ok += wanted.assets.len() - found.missing(&wanted.assets).len();
+                collected_links.push((file_name, found));
+            }
+            Err(failure) => failures.push(failure),
+        }
+    }
+    matches.save(&paths.matches).path(&paths.matches)?;
+    manifest.save(&paths.manifest).path(&paths.manifest)?;
+    facts.save(&paths.facts).path(&paths.facts)?;
+    log!(
+        [LogStyle::Blue],
+        "SCRAPER",
+        "Chosen pages and rejected candidates saved to {:?}, links to download to {:?}, \
+         infobox facts and summaries to {:?}",
+        paths.matches,
+        paths.manifest,
+        paths.facts
+    );
 
     Ok((
         ReturnReport {
             job_name: "SCRAPER".into(),
             duration: start_time.elapsed(),
-            amount_ok: collected_links.len(),
-            amount_err: total_downloads - collected_links.len(),
+            amount_ok,
+            amount_err: total_assets - amount_ok,
+            failures,
+            overrides: used_overrides,
+            bytes_downloaded: 0,
         },
         collected_links,
     ))
 }
 
-async fn download_image(
-    client: Arc<reqwest::Client>,
-    link: &str,
-    file_name: &str,
-    folder: &Path,
-    counter: Arc<AtomicU32>,
-    total: usize,
-) -> AppResult<()> {
-    let default_type = &reqwest::header::HeaderValue::from_static("image/raw");
-    let res = client
-        .get(link)
-        .send()
-        .await
-        .inspect_err(|err| log!([LogStyle::Bold, LogStyle::Red], "CRITICAL ERROR", "{err}"))?;
+/// Fetches `link`, whatever its Content-Type says the body is checked by [`validate`]
+async fn fetch_image(http: &Http, link: &str, file_name: &str) -> AppResult<Vec<u8>> {
+    let res = http.get(link).await.city(file_name).inspect_err(
+        |err| log!(Error: [LogStyle::Bold, LogStyle::Red], "CRITICAL ERROR", "{err}"),
+    )?;
 
     if let Err(err) = res.error_for_status_ref() {
         log!(
-            [LogStyle::Red],
+            Error: [LogStyle::Red],
             "ERR",
-            "Couldn't download COA for {file_name}: Server returned {}{} {}{}",
+            "Couldn't download image for {file_name}: Server returned {}{} {}{}",
             LogStyle::Italic,
             err.status().unwrap().as_u16(),
             err.status().unwrap().canonical_reason().unwrap(),
             LogStyle::Clear,
         );
 
-        return Err(err.into());
+        return Err(AppError::from(err).with_url(link).with_city(file_name));
     }
 
-    let extension = res
-        .headers()
-        .get("Content-Type")
-        .unwrap_or(default_type)
-        .to_str()
-        .map_err(|_| AppError::Other("request conversion error".into()))
-        .inspect_err(|err| log!([LogStyle::Bold, LogStyle::Red], "CRITICAL ERROR", "{err}"))?
-        .trim_start_matches("image/")
-        .trim_end_matches("+xml");
-
-    let file_path = folder.join(format!("{file_name}.{extension}"));
-    let bytes = res.bytes().await.inspect_err(|err| {
-        log!(
-            [LogStyle::Red],
-            "ERR",
-            "Couldn't fetch bytes from the server: {err}"
-        )
-    })?;
+    let bytes = res
+        .bytes()
+        .await
+        .url(link)
+        .city(file_name)
+        .inspect_err(|err| {
+            log!(
+                Error: [LogStyle::Red],
+                "ERR",
+                "Couldn't fetch bytes from the server: {err}"
+            )
+        })?;
 
-    log!(
+    Ok(bytes.into())
+}
+
+/// Removes files of the same city with another extension, left over from an earlier source
+fn remove_stale(folder: &Path, file_name: &str, keep: &Path) -> AppResult<()> {
+    for entry in fs::read_dir(folder).path(folder)? {
+        let path = entry?.path();
+        if path != keep && file_stem(&path).as_deref() == Some(file_name) {
+            fs::remove_file(&path).path(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the image and its credit to `folder` as `stem`, named after the format its bytes are,
+/// returning what was saved and how many bytes came over the network.
+/// Downloads that aren't a whole image are put in [`Paths::quarantine`] instead
+async fn save_image(
+    http: &Http,
+    link: &ImageLink,
+    paths: &Paths,
+    stem: &str,
+    folder: &Path,
+) -> AppResult<(Download, u64)> {
+    let (from, bytes) = match &link.source {
+        ImageSource::Url(link) => (link.clone(), fetch_image(http, link, stem).await?),
+        ImageSource::File(path) => {
+            let path = paths.data.join(path);
+            let bytes = fs::read(&path).path(&path).city(stem).inspect_err(
+                |err| log!(Error: [LogStyle::Red], "ERR", "Couldn't read override: {err}"),
+            )?;
+            (path.display().to_string(), bytes)
+        }
+    };
+
+    let downloaded = match &link.source {
+        ImageSource::Url(_) => bytes.len() as u64,
+        ImageSource::File(_) => 0,
+    };
+    let (bytes, validated) = validate_blocking(bytes).await;
+    let format = match validated {
+        Ok(format) => format,
+        Err(reason) => {
+            // an override is a file of the user's own, there's nothing to keep
+            if let ImageSource::Url(_) = &link.source {
+                let kept = quarantine_bytes(&paths.quarantine, &format!("{stem}.bin"), &bytes)
+                    .city(stem)?;
+                log!(
+                    Warn: [LogStyle::Yellow],
+                    "QUARANTINE",
+                    "{from}: {reason}, kept as {kept:?}"
+                );
+            }
+            return Err(AppError::from(reason).with_url(&from).with_city(stem));
+        }
+    };
+
+    let file_path = folder.join(format!("{stem}.{}", format.extension()));
+    let relative = file_path.strip_prefix(&paths.data).unwrap_or(&file_path);
+    let download = Download::new(relative.to_owned(), from, &bytes);
+
+    write_atomic(&file_path, &bytes)
+        .city(stem)
+        .inspect_err(|err| {
+            log!(
+                Error: [LogStyle::Bold, LogStyle::Red],
+                "CRITICAL ERROR",
+                "Failed to write: {err}"
+            )
+        })?;
+
+    remove_stale(folder, stem, &file_path).city(stem)?;
+    link.credit.save(&Credit::path(folder, stem)).city(stem)?;
+
+    Ok((download, downloaded))
+}
+
+/// Saves the image and its credit, returning what was saved and how many bytes came over
+/// the network
+async fn download_image(
+    http: &Http,
+    link: &ImageLink,
+    paths: &Paths,
+    file_name: &str,
+    folder: &Path,
+    progress: &StageProgress,
+) -> AppResult<(Download, u64)> {
+    let (download, downloaded) = save_image(http, link, paths, file_name, folder).await?;
+
+    log_msg(
+        progress.item_level(),
         [LogStyle::Green],
         &format!(
             "OK{:>13}",
-            format!("{}/{total}", counter.fetch_add(1, Ordering::Relaxed) + 1)
+            format!("{}/{}", progress.advance(), progress.total())
+        ),
+        format!(
+            "Downloaded! Saved to {}{:?}{}",
+            LogStyle::Cyan,
+            paths.data.join(&download.path),
+            LogStyle::Clear
         ),
-        "Downloaded! Saving to {}{file_path:?}{}",
-        LogStyle::Cyan,
-        LogStyle::Clear
     );
 
-    fs::write(file_path, bytes).inspect_err(|err| {
-        log!(
-            [LogStyle::Bold, LogStyle::Red],
-            "CRITICAL ERROR",
-            "Failed to write: {err}"
-        )
-    })?;
+    Ok((download, downloaded))
+}
 
-    Ok(())
+/// Saves the first of the ranked `links` that downloads as the background and the ones after
+/// it as spares, numbered from 1 in [`Paths::spare_backgrounds`]
+async fn download_backgrounds(
+    http: &Http,
+    links: &[ImageLink],
+    paths: &Paths,
+    file_name: &str,
+    progress: &StageProgress,
+) -> AppResult<(Download, Vec<Download>, u64)> {
+    let mut links = links.iter();
+    let mut bytes = 0;
+    let download = loop {
+        let Some(link) = links.next() else {
+            return Err(AppError::from(Reason::NoMatchingPage).with_city(file_name));
+        };
+
+        match download_image(http, link, paths, file_name, &paths.backgrounds, progress).await {
+            Ok((download, downloaded)) => {
+                bytes += downloaded;
+                break download;
+            }
+            Err(err) if links.len() > 0 => log!(
+                Warn: [LogStyle::Yellow],
+                "FALLBACK",
+                "Trying the next background for {file_name}: {err}"
+            ),
+            Err(err) => return Err(err),
+        }
+    };
+
+    for (_, path) in paths.spare_backgrounds_of(file_name)? {
+        fs::remove_file(&path).path(&path)?;
+    }
+
+    let mut spares = Vec::new();
+    for (rank, link) in links.enumerate() {
+        let stem = format!("{file_name}.{}", rank + 1);
+        match save_image(http, link, paths, &stem, &paths.spare_backgrounds).await {
+            Ok((spare, downloaded)) => {
+                bytes += downloaded;
+                spares.push(spare);
+            }
+            Err(err) => log!(
+                Debug: [LogStyle::Yellow],
+                "SPARE",
+                "Skipping a spare background of {file_name}: {err}"
+            ),
+        }
+    }
+
+    Ok((download, spares, bytes))
 }
 
+/// Downloads the `links`, recording what was saved in [`Paths::manifest`]
 pub async fn download_assets(
     links: Vec<(String, Links)>,
     paths: &Paths,
+    http: Arc<Http>,
+    concurrency: usize,
 ) -> AppResult<ReturnReport> {
     let start_time = time::Instant::now();
 
     ensure_exists(&paths.coas)?;
     ensure_exists(&paths.backgrounds)?;
+    ensure_exists(&paths.spare_backgrounds)?;
 
-    let client = Arc::new(reqwest::Client::builder().user_agent(USER_AGENT).build()?);
-    let counter = Arc::new(AtomicU32::new(0));
     let paths = Arc::new(paths.clone());
-    let links: Vec<Arc<(String, Links)>> = links.into_iter().map(Arc::new).collect();
-    let total_to_download = links.len() * 2;
-    let mut total_downloaded = vec![];
-
-    for chunk in links.chunks(CONCURRENT_DOWNLOADS) {
-        let mut join_set: JoinSet<AppResult<()>> = JoinSet::new();
-
-        for data in chunk {
-            let data = data.clone();
-            let client = client.clone();
-            let paths = paths.clone();
-            let counter = counter.clone();
-
-            join_set.spawn(async move {
-                download_image(
-                    client.clone(),
-                    &data.1.coa_link,
-                    &data.0,
-                    &paths.coas,
-                    counter.clone(),
-                    total_to_download,
-                )
-                .await?;
-                download_image(
-                    client,
-                    &data.1.bg_link,
-                    &data.0,
-                    &paths.backgrounds,
-                    counter,
-                    total_to_download,
-                )
-                .await?;
+    let total_to_download = links
+        .iter()
+        .map(|(_, links)| links.coa.iter().count() + usize::from(!links.bg.is_empty()))
+        .sum();
+    let progress = StageProgress::new("DOWNLOADER", total_to_download);
 
-                Ok(())
-            });
+    let downloaded = run_bounded(links, concurrency, |(file_name, links)| {
+        let http = http.clone();
+        let paths = paths.clone();
+        let progress = progress.clone();
+
+        async move {
+            let mut results = Vec::with_capacity(2);
+            if let Some(coa) = &links.coa {
+                let res = download_image(&http, coa, &paths, &file_name, &paths.coas, &progress);
+                let res = res.await.map(|(download, bytes)| (download, vec![], bytes));
+                results.push((AssetKind::Coa, res));
+            }
+            if !links.bg.is_empty() {
+                let res = download_backgrounds(&http, &links.bg, &paths, &file_name, &progress);
+                results.push((AssetKind::Background, res.await));
+            }
+
+            // the kinds are independent, one failing doesn't stop the other
+            let mut bytes = 0;
+            let mut saved = Vec::new();
+            let mut failures = Vec::new();
+            for (kind, res) in results {
+                match res.stage(Stage::Downloader) {
+                    Ok((download, spares, downloaded)) => {
+                        bytes += downloaded;
+                        saved.push((file_name.clone(), kind, download, spares));
+                    }
+                    Err(err) => {
+                        // the image was never downloaded, yet it still counts as processed
+                        progress.advance();
+                        progress.fail(&file_name, &err.kind);
+                        failures.push(Failure::new(&file_name, &err).with_asset(kind));
+                    }
+                }
+            }
+
+            (bytes, saved, failures)
         }
+    })
+    .await;
+    progress.finish();
 
-        let mut vec = join_set.join_all().await;
-        total_downloaded.append(&mut vec);
+    let mut manifest = Manifest::load(&paths.manifest).path(&paths.manifest)?;
+    let mut bytes_downloaded = 0;
+    let mut failures = Vec::new();
+    for (bytes, saved, city_failures) in downloaded {
+        bytes_downloaded += bytes;
+        for (file_name, kind, download, spares) in saved {
+            manifest.record_download(&file_name, kind, download, spares);
+        }
+        failures.extend(city_failures);
     }
-    let total_downloaded = total_downloaded.into_iter().filter(Result::is_ok).count() * 2;
+    manifest.save(&paths.manifest).path(&paths.manifest)?;
+
+    let total_downloaded = total_to_download - failures.len();
 
     Ok(ReturnReport {
         job_name: "DOWNLOADER".into(),
         duration: start_time.elapsed(),
         amount_ok: total_downloaded,
         amount_err: total_to_download - total_downloaded,
+        failures,
+        overrides: vec![],
+        bytes_downloaded,
     })
 }
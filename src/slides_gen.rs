@@ -1,30 +1,91 @@
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
 use crate::{
+    config::SlidesConfig,
+    credits,
+    parser::VOIVODESHIP_COUNT,
+    utils::{ReturnReport, ensure_exists, format_file_name},
+};
+use crate::{
+    credits::{CityCredits, Credit},
+    facts::FactKind,
     log,
-    logger::{LogStyle, log_msg},
-    parser::{City, VOIVODESHIP_COUNT, Voivodeship},
+    logger::LogStyle,
+    parser::{City, Voivodeship},
     paths::Paths,
-    utils::{AppResult, ReturnReport, capitalize, ensure_exists, format_file_name},
+    utils::{AppResult, Context, capitalize},
 };
-use ab_glyph::{FontRef, PxScale};
-use image::{ImageBuffer, ImageFormat, Rgba, RgbaImage, imageops::overlay};
+use ab_glyph::{FontArc, PxScale};
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+use image::ImageFormat;
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage, imageops::overlay};
 use imageproc::drawing::{draw_text_mut, text_size};
-use std::fs::{File, read};
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::{fs::read, path::Path};
+
+/// Cities shown on a single slide, 1080 / 4 = 270 pixels per entry
+pub const CITIES_PER_SLIDE: usize = 4;
 
-struct Fonts<'a> {
-    regular: FontRef<'a>,
-    bold: FontRef<'a>,
+/// Lines of a single credits slide, 32 pixels each below the heading
+pub const CREDITS_PER_SLIDE: usize = 28;
+
+struct Fonts {
+    regular: FontArc,
+    bold: FontArc,
 }
 
 struct Icons {
-    home: ImageBuffer<Rgba<u8>, Vec<u8>>,
-    population: ImageBuffer<Rgba<u8>, Vec<u8>>,
-    area: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    home: RgbaImage,
+    population: RgbaImage,
+    area: RgbaImage,
+}
+
+/// Format slides are written in, picked by the `export-*` feature
+#[cfg(feature = "export-webp")]
+pub const SLIDE_FORMAT: ImageFormat = ImageFormat::WebP;
+/// Format slides are written in, picked by the `export-*` feature
+#[cfg(all(feature = "export-png", not(feature = "export-webp")))]
+pub const SLIDE_FORMAT: ImageFormat = ImageFormat::Png;
+
+/// A rendered slide together with the name it gets in the slides directory, without an extension
+pub struct Slide {
+    /// E.g. `0_dolnośląskie_1`
+    pub name: String,
+    /// Always 1920×1080
+    pub image: RgbaImage,
+}
+
+impl Slide {
+    /// Encodes the slide as [`SLIDE_FORMAT`] into `directory`
+    #[cfg(any(feature = "export-webp", feature = "export-png"))]
+    pub fn save(&self, directory: &Path) -> AppResult<()> {
+        let path = directory.join(format!(
+            "{}.{}",
+            self.name,
+            SLIDE_FORMAT.extensions_str()[0]
+        ));
+        self.image.save_with_format(&path, SLIDE_FORMAT).path(&path)
+    }
+}
+
+/// Number of numbered slides of a voivodeship, its title slide plus one per 4 cities
+pub fn slide_count(voivodeship: &Voivodeship) -> usize {
+    1 + voivodeship.content.len().div_ceil(CITIES_PER_SLIDE)
+}
+
+fn open_image(path: &Path) -> AppResult<DynamicImage> {
+    image::open(path).path(path)
+}
+
+fn read_font(path: &Path) -> AppResult<FontArc> {
+    let data = read(path).path(path)?;
+    FontArc::try_from_vec(data).path(path)
 }
 
 fn draw_text(
     img: &mut RgbaImage,
     text: &str,
-    font: &FontRef,
+    font: &FontArc,
     x: i32,
     y: i32,
     font_size: f32,
@@ -37,298 +98,544 @@ fn draw_text(
     for dx in -1..=1 {
         for dy in -1..=1 {
             if dx != 0 || dy != 0 {
-                draw_text_mut(
-                    img,
-                    Rgba([0, 0, 0, 255]),
-                    x + dx,
-                    y + dy,
-                    scale,
-                    &font,
-                    text,
-                );
+                draw_text_mut(img, Rgba([0, 0, 0, 255]), x + dx, y + dy, scale, font, text);
             }
         }
     }
 
-    draw_text_mut(img, color, x, y, scale, &font, text);
+    draw_text_mut(img, color, x, y, scale, font, text);
 
-    text_size(scale, &font, text)
+    text_size(scale, font, text)
 }
 
-fn generate_title(font: &Fonts, voivodeship: &str) -> AppResult<ImageBuffer<Rgba<u8>, Vec<u8>>> {
-    let mut image = ImageBuffer::from_pixel(1920, 1080, Rgba([0, 0, 0, 255]));
+/// Renders slides into memory, fonts and icons are loaded once and shared by every slide
+pub struct SlideRenderer<'a> {
+    paths: &'a Paths,
+    fonts: Fonts,
+    icons: Icons,
+    captions: bool,
+    facts: Vec<FactKind>,
+}
 
-    let text = format!("woj. {}", capitalize(voivodeship));
+impl<'a> SlideRenderer<'a> {
+    /// Loads the fonts and icons, without captions or facts
+    pub fn load(paths: &'a Paths) -> AppResult<Self> {
+        log!([LogStyle::Blue], "PRES GEN", "Loading fonts...");
+
+        let fonts = Fonts {
+            regular: read_font(&paths.fonts.join("BonaNova-Regular-Lining.ttf"))?,
+            bold: read_font(&paths.fonts.join("BonaNova-Bold-Lining.ttf"))?,
+        };
+
+        log!([LogStyle::Blue], "PRES GEN", "Loading icons...");
+
+        let icons = Icons {
+            home: open_image(&paths.icons.join("home.png"))?.to_rgba8(),
+            area: open_image(&paths.icons.join("area.png"))?.to_rgba8(),
+            population: open_image(&paths.icons.join("population.png"))?.to_rgba8(),
+        };
+
+        Ok(Self {
+            paths,
+            fonts,
+            icons,
+            captions: false,
+            facts: vec![],
+        })
+    }
 
-    let (width, height) = text_size(PxScale::from(100.0), &font.bold, &text);
-    let x = image.width() / 2 - width / 2;
-    let y = image.height() / 2 - height / 2;
+    /// Puts the author and licence of the background on every entry that has them
+    pub fn with_captions(mut self, captions: bool) -> Self {
+        self.captions = captions;
+        self
+    }
 
-    draw_text(
-        &mut image,
-        &text,
-        &font.bold,
-        x as i32,
-        y as i32,
-        100.0,
-        Rgba([255, 255, 255, 255]),
-    );
+    /// Puts the `facts` every city has of them under its powiat, in that order
+    pub fn with_facts(mut self, facts: Vec<FactKind>) -> Self {
+        self.facts = facts;
+        self
+    }
 
-    Ok(image)
-}
+    /// The first slide of the presentation, `credits` go to the bottom right corner
+    pub fn cover(&self, credits: &str) -> RgbaImage {
+        let mut image = ImageBuffer::from_pixel(1920, 1080, Rgba([0, 0, 0, 255]));
+        let text = "Podział Administracyjny Polski";
+
+        let (width, height) = text_size(PxScale::from(140.0), &self.fonts.bold, text);
+        let x = image.width() / 2 - width / 2;
+        let y = image.height() / 2 - height / 2;
+
+        draw_text(
+            &mut image,
+            text,
+            &self.fonts.bold,
+            x as i32,
+            y as i32,
+            140.0,
+            Rgba([240, 240, 240, 255]),
+        );
 
-fn generate_map_slide(
-    paths: &Paths,
-    font: &Fonts,
-    icons: &Icons,
-    voivodeship: &Voivodeship,
-) -> AppResult<ImageBuffer<Rgba<u8>, Vec<u8>>> {
-    const PADDING: u32 = 64;
-    const DIMENSIONS: (u32, u32) = (1920, 1080);
-
-    let mut image = ImageBuffer::from_pixel(DIMENSIONS.0, DIMENSIONS.1, Rgba([0, 0, 0, 255]));
-
-    let map_path = paths
-        .maps
-        .join(format!("{}_transparent.png", voivodeship.name));
-
-    let mut map = image::open(map_path)?;
-
-    const ASPECT_RATIO: u32 = DIMENSIONS.0 / DIMENSIONS.1;
-    const NEW_HEIGHT: u32 = ASPECT_RATIO * DIMENSIONS.1 - (PADDING * 2);
-    const NEW_WIDTH: u32 = NEW_HEIGHT * ASPECT_RATIO;
-
-    map = map.resize_exact(NEW_WIDTH, NEW_HEIGHT, image::imageops::FilterType::Lanczos3);
-    overlay(&mut image, &map.to_rgba8(), PADDING.into(), PADDING.into());
-
-    let mut text_offset: (u32, u32) = (PADDING * 3 + NEW_WIDTH, PADDING * 2);
-
-    let text = format!("woj. {}", capitalize(&voivodeship.name));
-    let stat_size = text_size(PxScale::from(80.0), &font.bold, &text);
-    draw_text(
-        &mut image,
-        &text,
-        &font.bold,
-        text_offset.0 as i32,
-        text_offset.1 as i32,
-        80.0,
-        Rgba([255, 255, 255, 255]),
-    );
+        let (width, height) = text_size(PxScale::from(32.0), &self.fonts.regular, credits);
+        let x = image.width() - 32 - width;
+        let y = image.height() - 32 - height;
+
+        draw_text(
+            &mut image,
+            credits,
+            &self.fonts.regular,
+            x as i32,
+            y as i32,
+            32.0,
+            Rgba([255, 255, 255, 255]),
+        );
 
-    text_offset.1 = text_offset.1 + stat_size.1 + 64;
-
-    let city_count = voivodeship.content.len();
-    let text = format!("{city_count} miast");
-    let stat_size = text_size(PxScale::from(64.0), &font.regular, &text);
-    let home_icon_y = text_offset.1 - (icons.home.height() / 2) + (stat_size.1 / 2);
-
-    draw_text(
-        &mut image,
-        &text,
-        &font.regular,
-        text_offset.0 as i32 + icons.home.width() as i32 + 32,
-        text_offset.1 as i32,
-        64.0,
-        Rgba([255, 255, 255, 255]),
-    );
-    overlay(
-        &mut image,
-        &icons.home,
-        text_offset.0 as i64,
-        home_icon_y as i64,
-    );
+        image
+    }
 
-    text_offset.1 = text_offset.1 + stat_size.1 + 64;
+    /// Slide opening a voivodeship, with its name centred
+    pub fn title(&self, voivodeship: &str) -> RgbaImage {
+        let mut image = ImageBuffer::from_pixel(1920, 1080, Rgba([0, 0, 0, 255]));
 
-    let text = format!(
-        "{} ({}/km²)",
-        voivodeship.total_population, voivodeship.population_per_km
-    );
-    let stat_size = text_size(PxScale::from(64.0), &font.regular, &text);
-    let population_icon_y = text_offset.1 - (icons.population.height() / 2) + (stat_size.1 / 2);
-
-    draw_text(
-        &mut image,
-        &text,
-        &font.regular,
-        text_offset.0 as i32 + icons.population.width() as i32 + 32,
-        text_offset.1 as i32,
-        64.0,
-        Rgba([255, 255, 255, 255]),
-    );
-    overlay(
-        &mut image,
-        &icons.population,
-        text_offset.0 as i64,
-        population_icon_y as i64,
-    );
+        let text = format!("woj. {}", capitalize(voivodeship));
 
-    text_offset.1 = text_offset.1 + stat_size.1 + 64;
-
-    let text = format!("{} km² ({} ha)", voivodeship.area_km, voivodeship.area_ha);
-    let stat_size = text_size(PxScale::from(64.0), &font.regular, &text);
-    let area_icon_y =
-        text_offset.1 as i32 - (icons.area.height() as i32 / 2) + (stat_size.1 as i32 / 2);
-
-    draw_text(
-        &mut image,
-        &text,
-        &font.regular,
-        text_offset.0 as i32 + icons.area.width() as i32 + 32,
-        text_offset.1 as i32,
-        64.0,
-        Rgba([255, 255, 255, 255]),
-    );
-    overlay(
-        &mut image,
-        &icons.area,
-        text_offset.0 as i64,
-        area_icon_y as i64,
-    );
+        let (width, height) = text_size(PxScale::from(100.0), &self.fonts.bold, &text);
+        let x = image.width() / 2 - width / 2;
+        let y = image.height() / 2 - height / 2;
 
-    Ok(image)
-}
+        draw_text(
+            &mut image,
+            &text,
+            &self.fonts.bold,
+            x as i32,
+            y as i32,
+            100.0,
+            Rgba([255, 255, 255, 255]),
+        );
 
-fn generate_entry(
-    paths: &Paths,
-    font: &Fonts,
-    icons: &Icons,
-    city: &City,
-) -> AppResult<ImageBuffer<Rgba<u8>, Vec<u8>>> {
-    let filename = format!("{}.webp", format_file_name(city));
-    let background_path = paths.edited_backgrounds.join(&filename);
-    let mut image = image::open(background_path)?.to_rgba8();
-    let img_height = image.height() as i32;
-    let img_width = image.width() as i32;
-
-    let coa_path = paths.edited_coas.join(filename);
-    let coa = image::open(coa_path)?.to_rgba8();
-    let coa_width = coa.width() as i32;
-    let coa_height = coa.height() as i32;
-    let coa_y = img_height / 2 - coa_height / 2;
-    overlay(&mut image, &coa, 32, coa_y as i64);
-
-    let text_offset = 64 + coa_width;
-
-    let name_text_size = text_size(PxScale::from(80.0), &font.bold, &city.name);
-    draw_text(
-        &mut image,
-        &city.name,
-        &font.bold,
-        text_offset,
-        img_height / 2 - name_text_size.1 as i32 - 8,
-        80.0,
-        Rgba([255, 255, 255, 255]),
-    );
+        image
+    }
 
-    draw_text(
-        &mut image,
-        &format!("powiat {}", &city.powiat),
-        &font.regular,
-        text_offset,
-        img_height / 2 + 8,
-        48.0,
-        Rgba([200, 200, 200, 255]),
-    );
+    /// Slide with the voivodeship's map and how many cities it has
+    pub fn map_slide(&self, voivodeship: &Voivodeship) -> AppResult<RgbaImage> {
+        const PADDING: u32 = 64;
+        const DIMENSIONS: (u32, u32) = (1920, 1080);
 
-    let population_text = format!("{} ({}/km²)", city.total_population, city.population_per_km);
-    let population_text_size = text_size(PxScale::from(48.0), &font.regular, &population_text);
-    let population_x = img_width - 32 - population_text_size.0 as i32;
-    let population_y = img_height / 2 - population_text_size.1 as i32 - 16;
-    let population_icon_y =
-        population_y - (icons.population.height() as i32 / 2) + (population_text_size.1 as i32 / 2);
-
-    let area_text = format!("{} km² ({} ha)", city.area_km, city.area_ha);
-    let area_text_size = text_size(PxScale::from(48.0), &font.regular, &area_text);
-    let area_x = img_width - 32 - area_text_size.0 as i32;
-    let area_y = img_height / 2 + 16;
-    let area_icon_y = area_y - (icons.area.height() as i32 / 2) + (area_text_size.1 as i32 / 2);
-
-    draw_text(
-        &mut image,
-        &population_text,
-        &font.regular,
-        population_x,
-        population_y,
-        48.0,
-        Rgba([255, 255, 255, 255]),
-    );
-    overlay(
-        &mut image,
-        &icons.population,
-        population_x.min(area_x) as i64 - icons.population.width() as i64 - 16,
-        population_icon_y as i64,
-    );
+        let (font, icons) = (&self.fonts, &self.icons);
+        let mut image = ImageBuffer::from_pixel(DIMENSIONS.0, DIMENSIONS.1, Rgba([0, 0, 0, 255]));
 
-    draw_text(
-        &mut image,
-        &area_text,
-        &font.regular,
-        area_x,
-        area_y,
-        48.0,
-        Rgba([255, 255, 255, 255]),
-    );
-    overlay(
-        &mut image,
-        &icons.area,
-        population_x.min(area_x) as i64 - icons.area.width() as i64 - 16,
-        area_icon_y as i64,
-    );
+        let map_path = self
+            .paths
+            .maps
+            .join(format!("{}_transparent.png", voivodeship.name));
 
-    Ok(image)
-}
+        let mut map = open_image(&map_path)?;
 
-fn generate_slide(
-    paths: &Paths,
-    font: &Fonts,
-    icons: &Icons,
-    cities: &[City],
-) -> AppResult<ImageBuffer<Rgba<u8>, Vec<u8>>> {
-    let entries = cities
-        .iter()
-        .map(|city| generate_entry(paths, font, icons, city))
-        .collect::<AppResult<Vec<_>>>()?;
+        const ASPECT_RATIO: u32 = DIMENSIONS.0 / DIMENSIONS.1;
+        const NEW_HEIGHT: u32 = ASPECT_RATIO * DIMENSIONS.1 - (PADDING * 2);
+        const NEW_WIDTH: u32 = NEW_HEIGHT * ASPECT_RATIO;
 
-    let mut canvas = ImageBuffer::from_pixel(1920, 1080, Rgba([0, 0, 0, 255]));
+        map = map.resize_exact(NEW_WIDTH, NEW_HEIGHT, image::imageops::FilterType::Lanczos3);
+        overlay(&mut image, &map.to_rgba8(), PADDING.into(), PADDING.into());
 
-    for (i, entry) in entries.iter().enumerate() {
-        overlay(&mut canvas, entry, 0, i as i64 * 270);
+        let mut text_offset: (u32, u32) = (PADDING * 3 + NEW_WIDTH, PADDING * 2);
+
+        let text = format!("woj. {}", capitalize(&voivodeship.name));
+        let stat_size = text_size(PxScale::from(80.0), &font.bold, &text);
+        draw_text(
+            &mut image,
+            &text,
+            &font.bold,
+            text_offset.0 as i32,
+            text_offset.1 as i32,
+            80.0,
+            Rgba([255, 255, 255, 255]),
+        );
+
+        text_offset.1 = text_offset.1 + stat_size.1 + 64;
+
+        let city_count = voivodeship.content.len();
+        let text = format!("{city_count} miast");
+        let stat_size = text_size(PxScale::from(64.0), &font.regular, &text);
+        let home_icon_y = text_offset.1 - (icons.home.height() / 2) + (stat_size.1 / 2);
+
+        draw_text(
+            &mut image,
+            &text,
+            &font.regular,
+            text_offset.0 as i32 + icons.home.width() as i32 + 32,
+            text_offset.1 as i32,
+            64.0,
+            Rgba([255, 255, 255, 255]),
+        );
+        overlay(
+            &mut image,
+            &icons.home,
+            text_offset.0 as i64,
+            home_icon_y as i64,
+        );
+
+        text_offset.1 = text_offset.1 + stat_size.1 + 64;
+
+        let text = format!(
+            "{} ({}/km²)",
+            voivodeship.total_population, voivodeship.population_per_km
+        );
+        let stat_size = text_size(PxScale::from(64.0), &font.regular, &text);
+        let population_icon_y = text_offset.1 - (icons.population.height() / 2) + (stat_size.1 / 2);
+
+        draw_text(
+            &mut image,
+            &text,
+            &font.regular,
+            text_offset.0 as i32 + icons.population.width() as i32 + 32,
+            text_offset.1 as i32,
+            64.0,
+            Rgba([255, 255, 255, 255]),
+        );
+        overlay(
+            &mut image,
+            &icons.population,
+            text_offset.0 as i64,
+            population_icon_y as i64,
+        );
+
+        text_offset.1 = text_offset.1 + stat_size.1 + 64;
+
+        let text = format!("{} km² ({} ha)", voivodeship.area_km, voivodeship.area_ha);
+        let stat_size = text_size(PxScale::from(64.0), &font.regular, &text);
+        let area_icon_y =
+            text_offset.1 as i32 - (icons.area.height() as i32 / 2) + (stat_size.1 as i32 / 2);
+
+        draw_text(
+            &mut image,
+            &text,
+            &font.regular,
+            text_offset.0 as i32 + icons.area.width() as i32 + 32,
+            text_offset.1 as i32,
+            64.0,
+            Rgba([255, 255, 255, 255]),
+        );
+        overlay(
+            &mut image,
+            &icons.area,
+            text_offset.0 as i64,
+            area_icon_y as i64,
+        );
+
+        Ok(image)
     }
 
-    Ok(canvas)
-}
+    /// A single 1920x270 strip with the city's edited background, coat of arms and stats.
+    /// Cities that only got one of the images go without the other one
+    pub fn entry(&self, city: &City) -> AppResult<RgbaImage> {
+        let (font, icons) = (&self.fonts, &self.icons);
+        let assets = self.paths.city_assets(city)?;
+
+        let mut image = if assets.edited_background.exists() {
+            open_image(&assets.edited_background)
+                .city(&city.identifier)?
+                .to_rgba8()
+        } else {
+            ImageBuffer::from_pixel(1920, 270, Rgba([24, 24, 24, 255]))
+        };
+        let img_height = image.height() as i32;
+        let img_width = image.width() as i32;
+
+        let mut text_offset = 32;
+        if assets.edited_coa.exists() {
+            let coa = open_image(&assets.edited_coa)
+                .city(&city.identifier)?
+                .to_rgba8();
+            let coa_height = coa.height() as i32;
+            let coa_y = img_height / 2 - coa_height / 2;
+            overlay(&mut image, &coa, 32, coa_y as i64);
+            text_offset += 32 + coa.width() as i32;
+        }
 
-pub fn generate_slides(paths: &Paths, dataset: &[Voivodeship]) -> AppResult<ReturnReport> {
-    let start_time = std::time::Instant::now();
-    ensure_exists(&paths.slides)?;
+        let name_text_size = text_size(PxScale::from(80.0), &font.bold, &city.name);
+        draw_text(
+            &mut image,
+            &city.name,
+            &font.bold,
+            text_offset,
+            img_height / 2 - name_text_size.1 as i32 - 8,
+            80.0,
+            Rgba([255, 255, 255, 255]),
+        );
+
+        draw_text(
+            &mut image,
+            &format!("powiat {}", &city.powiat),
+            &font.regular,
+            text_offset,
+            img_height / 2 + 8,
+            48.0,
+            Rgba([200, 200, 200, 255]),
+        );
+
+        let facts: Vec<_> = self
+            .facts
+            .iter()
+            .filter_map(|&kind| city.facts.display(kind))
+            .collect();
+        if !facts.is_empty() {
+            draw_text(
+                &mut image,
+                &facts.join("  ·  "),
+                &font.regular,
+                text_offset,
+                img_height / 2 + 64,
+                32.0,
+                Rgba([200, 200, 200, 255]),
+            );
+        }
+
+        let population_text = format!("{} ({}/km²)", city.total_population, city.population_per_km);
+        let population_text_size = text_size(PxScale::from(48.0), &font.regular, &population_text);
+        let population_x = img_width - 32 - population_text_size.0 as i32;
+        let population_y = img_height / 2 - population_text_size.1 as i32 - 16;
+        let population_icon_y = population_y - (icons.population.height() as i32 / 2)
+            + (population_text_size.1 as i32 / 2);
+
+        let area_text = format!("{} km² ({} ha)", city.area_km, city.area_ha);
+        let area_text_size = text_size(PxScale::from(48.0), &font.regular, &area_text);
+        let area_x = img_width - 32 - area_text_size.0 as i32;
+        let area_y = img_height / 2 + 16;
+        let area_icon_y = area_y - (icons.area.height() as i32 / 2) + (area_text_size.1 as i32 / 2);
+
+        draw_text(
+            &mut image,
+            &population_text,
+            &font.regular,
+            population_x,
+            population_y,
+            48.0,
+            Rgba([255, 255, 255, 255]),
+        );
+        overlay(
+            &mut image,
+            &icons.population,
+            population_x.min(area_x) as i64 - icons.population.width() as i64 - 16,
+            population_icon_y as i64,
+        );
+
+        draw_text(
+            &mut image,
+            &area_text,
+            &font.regular,
+            area_x,
+            area_y,
+            48.0,
+            Rgba([255, 255, 255, 255]),
+        );
+        overlay(
+            &mut image,
+            &icons.area,
+            population_x.min(area_x) as i64 - icons.area.width() as i64 - 16,
+            area_icon_y as i64,
+        );
+
+        if self.captions
+            && let Some(credit) = Credit::load(&assets.background_credit).city(&city.identifier)?
+        {
+            let caption = format!("fot. {}", credit.short());
+            let (width, height) = text_size(PxScale::from(20.0), &font.regular, &caption);
+            draw_text(
+                &mut image,
+                &caption,
+                &font.regular,
+                img_width - 16 - width as i32,
+                img_height - 12 - height as i32,
+                20.0,
+                Rgba([200, 200, 200, 255]),
+            );
+        }
 
-    log!([LogStyle::Blue], "PRES GEN", "Loading fonts...");
+        Ok(image)
+    }
+
+    /// Slides listing the authors and licences of every image, `CREDITS_PER_SLIDE` cities each
+    pub(crate) fn credits_slides(&self, credits: &[CityCredits]) -> Vec<RgbaImage> {
+        let pages = credits.len().div_ceil(CREDITS_PER_SLIDE);
+
+        credits
+            .chunks(CREDITS_PER_SLIDE)
+            .enumerate()
+            .map(|(page, chunk)| {
+                let mut image = ImageBuffer::from_pixel(1920, 1080, Rgba([0, 0, 0, 255]));
+
+                let heading = format!("Źródła ilustracji ({}/{pages})", page + 1);
+                draw_text(
+                    &mut image,
+                    &heading,
+                    &self.fonts.bold,
+                    64,
+                    48,
+                    48.0,
+                    Rgba([255, 255, 255, 255]),
+                );
 
-    let regular_font_data = read(paths.fonts.join("BonaNova-Regular-Lining.ttf"))?;
-    let bold_font_data = read(paths.fonts.join("BonaNova-Bold-Lining.ttf"))?;
+                for (i, city) in chunk.iter().enumerate() {
+                    draw_text(
+                        &mut image,
+                        &city.line(),
+                        &self.fonts.regular,
+                        64,
+                        136 + i as i32 * 32,
+                        24.0,
+                        Rgba([220, 220, 220, 255]),
+                    );
+                }
+
+                image
+            })
+            .collect()
+    }
 
-    let fonts = Fonts {
-        regular: FontRef::try_from_slice(&regular_font_data)?,
-        bold: FontRef::try_from_slice(&bold_font_data)?,
-    };
+    /// Up to 4 city entries stacked on top of each other, numbered in the bottom right corner
+    pub fn cities_slide(&self, cities: &[City], slide_number: usize) -> AppResult<RgbaImage> {
+        let entries = cities
+            .iter()
+            .map(|city| self.entry(city))
+            .collect::<AppResult<Vec<_>>>()?;
 
-    log!([LogStyle::Blue], "PRES GEN", "Loading icons...");
+        let mut canvas = ImageBuffer::from_pixel(1920, 1080, Rgba([0, 0, 0, 255]));
 
-    let home_icon = image::open(paths.icons.join("home.png"))?;
-    let area_icon = image::open(paths.icons.join("area.png"))?;
-    let population_icon = image::open(paths.icons.join("population.png"))?;
+        for (i, entry) in entries.iter().enumerate() {
+            overlay(&mut canvas, entry, 0, i as i64 * 270);
+        }
 
-    let icons = Icons {
-        home: home_icon.to_rgba8(),
-        area: area_icon.to_rgba8(),
-        population: population_icon.to_rgba8(),
-    };
+        let slide_number_str = slide_number.to_string();
+        let (width, height) = text_size(PxScale::from(48.0), &self.fonts.bold, &slide_number_str);
+        let x = canvas.width() - width - 32;
+        let y = canvas.height() - height - 32;
+        draw_text(
+            &mut canvas,
+            &slide_number_str,
+            &self.fonts.bold,
+            x as i32,
+            y as i32,
+            48.0,
+            Rgba([255, 255, 255, 255]),
+        );
+
+        Ok(canvas)
+    }
+
+    /// Every slide of a voivodeship: its title, the map and the cities,
+    /// `slide_number` is the number of the title slide in the whole presentation
+    pub fn voivodeship_slides(
+        &self,
+        voivodeship_idx: usize,
+        voivodeship: &Voivodeship,
+        slide_number: usize,
+    ) -> AppResult<Vec<Slide>> {
+        let mut slides = vec![
+            Slide {
+                name: format!("{}_{}", voivodeship_idx, voivodeship.name),
+                image: self.title(&voivodeship.name),
+            },
+            Slide {
+                name: format!("{}_{}_0", voivodeship_idx, voivodeship.name),
+                image: self.map_slide(voivodeship)?,
+            },
+        ];
+
+        for (slide_index, city_chunk) in voivodeship.content.chunks(CITIES_PER_SLIDE).enumerate() {
+            slides.push(Slide {
+                name: cities_slide_name(voivodeship_idx, voivodeship, slide_index),
+                image: self.cities_slide(city_chunk, slide_number + slide_index + 1)?,
+            });
+
+            log!(
+                Debug: [LogStyle::Green],
+                "PRES GEN",
+                "Generated slide {slide_index} for {}",
+                voivodeship.name
+            );
+        }
+
+        Ok(slides)
+    }
+}
+
+/// Renders and saves the cover, every voivodeship and the credits
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+pub fn generate_slides(
+    paths: &Paths,
+    dataset: &[Voivodeship],
+    config: &SlidesConfig,
+) -> AppResult<ReturnReport> {
+    render_slides(paths, dataset, config, None, true)
+}
+
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+/// Renders the slides of a single voivodeship, leaving the other ones and the title slide as they are
+pub fn generate_voivodeship_slides(
+    paths: &Paths,
+    dataset: &[Voivodeship],
+    voivodeship_idx: usize,
+    config: &SlidesConfig,
+) -> AppResult<ReturnReport> {
+    render_slides(
+        paths,
+        dataset,
+        config,
+        Some(&BTreeSet::from([voivodeship_idx])),
+        false,
+    )
+}
+
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+/// Renders the slides of the voivodeships the cities with these file stems are in,
+/// and the credits, which list their images
+pub fn generate_city_slides(
+    paths: &Paths,
+    dataset: &[Voivodeship],
+    stems: &HashSet<String>,
+    config: &SlidesConfig,
+) -> AppResult<ReturnReport> {
+    let voivodeships = dataset
+        .iter()
+        .enumerate()
+        .filter(|(_, voivodeship)| {
+            voivodeship
+                .content
+                .iter()
+                .any(|city| stems.contains(&format_file_name(city)))
+        })
+        .map(|(idx, _)| idx)
+        .collect();
+    render_slides(paths, dataset, config, Some(&voivodeships), true)
+}
+
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+fn render_slides(
+    paths: &Paths,
+    dataset: &[Voivodeship],
+    config: &SlidesConfig,
+    only: Option<&BTreeSet<usize>>,
+    credits: bool,
+) -> AppResult<ReturnReport> {
+    let start_time = std::time::Instant::now();
+    ensure_exists(&paths.slides)?;
+
+    let renderer = SlideRenderer::load(paths)?
+        .with_captions(config.captions)
+        .with_facts(config.facts.clone());
 
     let mut amount_ok = 0;
-    let mut slide_number = 0;
+    let mut slide_number = 1;
 
     for (voivodeship_idx, voivodeship) in dataset.iter().enumerate() {
+        let first_slide = slide_number;
+        // keep numbering consistent with a full run when rendering a single voivodeship
+        slide_number += slide_count(voivodeship);
+
+        if only.is_some_and(|only| !only.contains(&voivodeship_idx)) {
+            continue;
+        }
+
         log!(
             [LogStyle::Blue, LogStyle::Bold],
             &format!(
@@ -339,106 +646,126 @@ pub fn generate_slides(paths: &Paths, dataset: &[Voivodeship]) -> AppResult<Retu
             voivodeship.name
         );
 
-        slide_number += 1;
+        for slide in renderer.voivodeship_slides(voivodeship_idx, voivodeship, first_slide)? {
+            slide.save(&paths.slides)?;
+        }
+
+        amount_ok += 1;
+    }
 
-        let slide = generate_title(&fonts, &voivodeship.name)?;
-        let slide_filename = format!("{}_{}.webp", amount_ok, voivodeship.name);
-        let slide_path = paths.slides.join(slide_filename);
-        slide.save_with_format(slide_path, ImageFormat::WebP)?;
+    let expected = only.map_or(VOIVODESHIP_COUNT, BTreeSet::len);
 
-        let slide = generate_map_slide(&paths, &fonts, &icons, &voivodeship)?;
-        let slide_filename = format!("{}_{}_0.webp", amount_ok, voivodeship.name);
-        let slide_path = paths.slides.join(slide_filename);
-        slide.save_with_format(slide_path, ImageFormat::WebP)?;
+    if only.is_none() {
+        generate_cover(paths, &renderer)?;
+    }
+    // every slide's notes, the ones left out this time are still in the presentation
+    write_notes(paths, dataset)?;
+    if credits {
+        generate_credits(paths, dataset, &renderer)?;
+    }
 
-        for (slide_index, city_chunk) in voivodeship.content.chunks(4).enumerate() {
-            slide_number += 1;
+    Ok(ReturnReport {
+        job_name: "PRES GEN".into(),
+        duration: start_time.elapsed(),
+        amount_ok,
+        amount_err: expected - amount_ok,
+        failures: vec![],
+        overrides: vec![],
+        bytes_downloaded: 0,
+    })
+}
 
-            let mut slide = generate_slide(paths, &fonts, &icons, city_chunk)?;
-            // add slide numbers
-            let slide_number_str = slide_number.to_string();
-            let (width, height) = text_size(PxScale::from(48.0), &fonts.bold, &slide_number_str);
-            let x = slide.width() - width - 32;
-            let y = slide.height() - height - 32;
-            draw_text(
-                &mut slide,
-                &slide_number_str,
-                &fonts.bold,
-                x as i32,
-                y as i32,
-                48.0,
-                Rgba([255, 255, 255, 255]),
-            );
+/// Name of the slide showing the `chunk`th group of [`CITIES_PER_SLIDE`] cities
+fn cities_slide_name(voivodeship_idx: usize, voivodeship: &Voivodeship, chunk: usize) -> String {
+    format!("{}_{}_{}", voivodeship_idx, voivodeship.name, chunk + 1)
+}
 
-            let slide_filename = format!(
-                "{}_{}_{}.webp",
-                amount_ok,
-                voivodeship.name,
-                slide_index + 1
-            );
-            let slide_path = paths.slides.join(slide_filename);
-            slide.save_with_format(slide_path, ImageFormat::WebP)?;
+/// Writes the summaries of the cities on every slide that has one, keyed by slide name
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+fn write_notes(paths: &Paths, dataset: &[Voivodeship]) -> AppResult<()> {
+    let mut notes = BTreeMap::new();
 
-            log!(
-                [LogStyle::Green],
-                "PRES GEN",
-                "Generated slide {slide_index} for {}",
-                voivodeship.name
-            );
+    for (voivodeship_idx, voivodeship) in dataset.iter().enumerate() {
+        for (chunk, cities) in voivodeship.content.chunks(CITIES_PER_SLIDE).enumerate() {
+            let summaries: Vec<_> = cities
+                .iter()
+                .filter_map(|city| {
+                    let summary = city.facts.summary.as_ref()?;
+                    Some(format!("{}: {}", city.name, summary.text))
+                })
+                .collect();
+
+            if !summaries.is_empty() {
+                notes.insert(
+                    cities_slide_name(voivodeship_idx, voivodeship, chunk),
+                    summaries.join("\n\n"),
+                );
+            }
         }
-
-        amount_ok += 1;
     }
 
+    std::fs::write(&paths.notes, serde_json::to_string_pretty(&notes)?).path(&paths.notes)
+}
+
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+fn generate_cover(paths: &Paths, renderer: &SlideRenderer) -> AppResult<()> {
     log!([LogStyle::Blue], "PRES GEN", "Generating title slide");
 
     let path = paths.data.join("credits.txt");
 
     if !path.exists() {
-        File::create(&path)?;
+        std::fs::File::create(&path)?;
     }
 
     let credits_raw = std::fs::read_to_string(path)?;
-    let credits = credits_raw.trim();
-
-    let mut image = ImageBuffer::from_pixel(1920, 1080, Rgba([0, 0, 0, 255]));
-    let text = "Podział Administracyjny Polski";
-
-    let (width, height) = text_size(PxScale::from(140.0), &fonts.bold, text);
-    let x = image.width() / 2 - width / 2;
-    let y = image.height() / 2 - height / 2;
-
-    draw_text(
-        &mut image,
-        text,
-        &fonts.bold,
-        x as i32,
-        y as i32,
-        140.0,
-        Rgba([240, 240, 240, 255]),
-    );
 
-    let (width, height) = text_size(PxScale::from(32.0), &fonts.regular, credits);
-    let x = image.width() - 32 - width;
-    let y = image.height() - 32 - height;
-
-    draw_text(
-        &mut image,
-        credits,
-        &fonts.regular,
-        x as i32,
-        y as i32,
-        32.0,
-        Rgba([255, 255, 255, 255]),
-    );
+    Slide {
+        name: "title".into(),
+        image: renderer.cover(credits_raw.trim()),
+    }
+    .save(&paths.slides)
+}
+
+/// Writes the attribution file and the credits slides, which come after every voivodeship
+#[cfg(any(feature = "export-webp", feature = "export-png"))]
+fn generate_credits(
+    paths: &Paths,
+    dataset: &[Voivodeship],
+    renderer: &SlideRenderer,
+) -> AppResult<()> {
+    log!([LogStyle::Blue], "PRES GEN", "Generating credits");
+
+    let cities = dataset.iter().flat_map(|voivodeship| &voivodeship.content);
+    let credits = credits::collect(paths, cities)?;
+    credits::write_attribution(&paths.attribution, &credits)?;
+
+    // numbered past the last voivodeship, so scripts/generate.ts puts them at the end
+    let prefix = format!("{VOIVODESHIP_COUNT}_credits_");
+    for entry in std::fs::read_dir(&paths.slides).path(&paths.slides)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+        {
+            std::fs::remove_file(&path).path(&path)?;
+        }
+    }
 
-    let slide_path = paths.slides.join("title.webp");
-    image.save_with_format(slide_path, ImageFormat::WebP)?;
+    for (page, image) in renderer.credits_slides(&credits).into_iter().enumerate() {
+        Slide {
+            name: format!("{prefix}{page}"),
+            image,
+        }
+        .save(&paths.slides)?;
+    }
 
-    Ok(ReturnReport {
-        job_name: "PRES GEN".into(),
-        duration: start_time.elapsed(),
-        amount_ok,
-        amount_err: VOIVODESHIP_COUNT - amount_ok,
-    })
+    log!(
+        [LogStyle::Blue],
+        "PRES GEN",
+        "Credited the images of {} cities in {:?}",
+        credits.len(),
+        paths.attribution
+    );
+
+    Ok(())
 }
@@ -1,27 +1,65 @@
 use crate::{logger::LogStyle, parser::City};
-use std::{fs, io, num::ParseIntError, path::Path};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    io,
+    num::ParseIntError,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 macro_rules! join_error {
-    ($visibility: vis enum $name: ident { $($memb: ident($err: ty)),* $(,)?}) => {
+    ($(#[$doc: meta])* $visibility: vis enum $name: ident for $wrapper: ident { $($(#[$meta: meta])* $memb: ident($err: ty)),* $(,)?}) => {
+        $(#[$doc])*
         #[derive(Debug)]
-        #[allow(dead_code)]
+        // every variant is named after the error it wraps
+        #[allow(dead_code, missing_docs)]
         $visibility enum $name {
-            $($memb($err)),*
+            $($(#[$meta])* $memb($err)),*
         }
 
         $(
+            $(#[$meta])*
             impl From<$err> for $name {
                 fn from(value: $err) -> $name {
                     $name::$memb(value)
                 }
             }
+
+            $(#[$meta])*
+            impl From<$err> for $wrapper {
+                fn from(value: $err) -> $wrapper {
+                    $wrapper::new($name::$memb(value))
+                }
+            }
         )*
 
+        impl $name {
+            /// Name of the variant, e.g. `Io`
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(
+                        $(#[$meta])*
+                        $name::$memb(_) => stringify!($memb),
+                    )*
+                }
+            }
+
+            fn inner(&self) -> &(dyn std::error::Error + 'static) {
+                match self {
+                    $(
+                        $(#[$meta])*
+                        $name::$memb(error) => error,
+                    )*
+                }
+            }
+        }
+
         impl std::error::Error for $name {}
         impl std::fmt::Display for $name {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 match self {
                     $(
+                        $(#[$meta])*
                         $name::$memb(error) => write!(f, "{error}"),
                     )*
                 }
@@ -30,27 +68,382 @@ macro_rules! join_error {
     }
 }
 
+/// Failures that come from the pipeline itself rather than from a library
+#[derive(Debug, Clone)]
+pub enum Reason {
+    /// A voivodeship row of the dataset doesn't start with `WOJ.`
+    NoVoivodeshipName,
+    /// None of the articles tried had the images looked for
+    NoMatchingPage,
+    /// An SVG that renders to an image without pixels
+    EmptyImage,
+    /// The binary was built without a cargo feature the requested command needs
+    MissingFeature(&'static str),
+    /// Error object returned by the MediaWiki API
+    WikiApi(String),
+    /// Running offline and the response was never fetched before
+    NotCached,
+    /// Every source was tried and none had a usable asset of this kind
+    NoAsset(AssetKind),
+    /// Downloaded bytes that aren't a whole image the editor can open
+    CorruptImage(String),
+    /// A config value that can't be used, e.g. a negative timeout
+    InvalidConfig(String),
+}
+
+impl std::error::Error for Reason {}
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::NoVoivodeshipName => write!(f, "no voivodeship name found"),
+            Reason::NoMatchingPage => write!(f, "no page with matching images found"),
+            Reason::EmptyImage => write!(f, "image has no pixels"),
+            Reason::MissingFeature(feature) => write!(
+                f,
+                "built without the `{feature}` feature, rebuild with `cargo build --features {feature}`"
+            ),
+            Reason::WikiApi(info) => write!(f, "wiki API returned an error: {info}"),
+            Reason::NotCached => write!(f, "not in the HTTP cache and running offline"),
+            Reason::NoAsset(kind) => write!(f, "no usable {kind} found"),
+            Reason::CorruptImage(problem) => write!(f, "corrupted image: {problem}"),
+            Reason::InvalidConfig(problem) => write!(f, "invalid config: {problem}"),
+        }
+    }
+}
+
 join_error! {
-    pub enum AppError {
+    /// What went wrong, the errors of the libraries the pipeline uses or a [`Reason`]
+    pub enum ErrorKind for AppError {
+        #[cfg(feature = "render")]
         UsvgError(resvg::usvg::Error),
+        #[cfg(feature = "render")]
         ImageError(image::error::ImageError),
         Io(io::Error),
+        #[cfg(feature = "scrape")]
         Request(reqwest::Error),
+        #[cfg(feature = "scrape")]
+        Header(reqwest::header::ToStrError),
+        #[cfg(feature = "render")]
         InvalidFont(ab_glyph::InvalidFont),
         ParseIntError(ParseIntError),
-        Other(String),
+        Json(serde_json::Error),
+        Toml(toml::de::Error),
+        Pipeline(Reason),
+    }
+}
+
+/// Part of the pipeline, errors and reports say which one they come from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    /// Loading the config and the files every stage needs
+    Setup,
+    /// Reading the dataset
+    Parser,
+    /// Finding the articles and images of the cities
+    Scraper,
+    /// Downloading the images the scraper found
+    Downloader,
+    /// Editing the downloaded images for the slides
+    ImageEditor,
+    /// Rendering the slides
+    SlidesGen,
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Stage::Setup => "setup",
+            Stage::Parser => "parser",
+            Stage::Scraper => "scraper",
+            Stage::Downloader => "downloader",
+            Stage::ImageEditor => "image editor",
+            Stage::SlidesGen => "slides gen",
+        })
+    }
+}
+
+/// Where in the pipeline an error happened, every field is optional since not
+/// every error is about a particular city or file
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ErrorContext {
+    /// Stage that was running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<Stage>,
+    /// File stem of the city
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    /// File that was being read or written
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Request that failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// Error of any stage of the pipeline, with where it happened
+#[derive(Debug)]
+pub struct AppError {
+    /// What went wrong
+    pub kind: ErrorKind,
+    // boxed to keep `AppResult` small on the happy path
+    /// Where it went wrong
+    pub context: Box<ErrorContext>,
+}
+
+impl AppError {
+    /// An error without any context yet, `From` conversions do the same
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            context: Box::default(),
+        }
+    }
+
+    // the setters keep the value that was set first, the innermost context is the most precise
+
+    /// Sets the stage, unless one is set already
+    pub fn with_stage(mut self, stage: Stage) -> Self {
+        self.context.stage.get_or_insert(stage);
+        self
+    }
+
+    /// Sets the city, unless one is set already
+    pub fn with_city(mut self, city: impl Into<String>) -> Self {
+        self.context.city.get_or_insert_with(|| city.into());
+        self
+    }
+
+    /// Sets the path, unless one is set already
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.context.path.get_or_insert_with(|| path.into());
+        self
+    }
+
+    /// Sets the URL, unless one is set already
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.context.url.get_or_insert_with(|| url.into());
+        self
+    }
+
+    /// Messages of the errors that caused this one, outermost first
+    pub fn causes(&self) -> Vec<String> {
+        let mut causes = vec![];
+        let mut previous = self.kind.to_string();
+        let mut source = self.kind.inner().source();
+        while let Some(error) = source {
+            let message = error.to_string();
+            // some errors already include their source in their own message
+            if !previous.contains(&message) {
+                causes.push(message.clone());
+            }
+            previous = message;
+            source = error.source();
+        }
+        causes
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.kind.inner())
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let context = &self.context;
+
+        if let Some(stage) = context.stage {
+            write!(f, "[{stage}] ")?;
+        }
+        if let Some(city) = &context.city {
+            write!(f, "{city}: ")?;
+        }
+
+        write!(f, "{}", self.kind)?;
+        for cause in self.causes() {
+            write!(f, ": {cause}")?;
+        }
+
+        match (&context.url, &context.path) {
+            (Some(url), Some(path)) => write!(f, " (url {url}, path {path:?})"),
+            (Some(url), None) => write!(f, " (url {url})"),
+            (None, Some(path)) => write!(f, " (path {path:?})"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Attaches [`ErrorContext`] to anything that converts into an [`AppError`]
+pub trait Context<T> {
+    /// See [`AppError::with_stage`]
+    fn stage(self, stage: Stage) -> AppResult<T>;
+    /// See [`AppError::with_city`]
+    fn city(self, city: &str) -> AppResult<T>;
+    /// See [`AppError::with_path`]
+    fn path(self, path: &Path) -> AppResult<T>;
+    /// See [`AppError::with_url`]
+    fn url(self, url: &str) -> AppResult<T>;
+}
+
+impl<T, E: Into<AppError>> Context<T> for Result<T, E> {
+    fn stage(self, stage: Stage) -> AppResult<T> {
+        self.map_err(|err| err.into().with_stage(stage))
+    }
+
+    fn city(self, city: &str) -> AppResult<T> {
+        self.map_err(|err| err.into().with_city(city))
+    }
+
+    fn path(self, path: &Path) -> AppResult<T> {
+        self.map_err(|err| err.into().with_path(path))
     }
+
+    fn url(self, url: &str) -> AppResult<T> {
+        self.map_err(|err| err.into().with_url(url))
+    }
+}
+
+/// Serializable snapshot of an [`AppError`], as stored in run reports
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ErrorReport {
+    /// See [`ErrorKind::name`]
+    pub kind: String,
+    /// Message of the error itself, without the causes
+    pub message: String,
+    /// Flattened into the report
+    #[serde(flatten)]
+    pub context: ErrorContext,
+    /// See [`AppError::causes`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub causes: Vec<String>,
 }
 
+impl From<&AppError> for ErrorReport {
+    fn from(value: &AppError) -> Self {
+        Self {
+            kind: value.kind.name().to_owned(),
+            message: value.kind.to_string(),
+            context: (*value.context).clone(),
+            causes: value.causes(),
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ErrorReport::from(self).serialize(serializer)
+    }
+}
+
+/// Result of anything in the pipeline that can fail
 pub type AppResult<T> = Result<T, AppError>;
 
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// The images every city entry is made of, scraped and downloaded independently
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetKind {
+    /// Coat of arms
+    Coa,
+    /// Photo of the city behind its entry
+    Background,
+}
+
+impl std::fmt::Display for AssetKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AssetKind::Coa => "coat of arms",
+            AssetKind::Background => "background",
+        })
+    }
+}
+
+/// A single item (city, file) that a stage failed to process
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Failure {
+    /// File stem of the city, or the name of the file
+    pub item: String,
+    /// Which of the city's images failed, `None` when the whole city did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<AssetKind>,
+    /// What went wrong
+    pub error: ErrorReport,
+}
+
+impl Failure {
+    /// The whole `item` failed with `error`
+    pub fn new(item: impl Into<String>, error: &AppError) -> Self {
+        Self {
+            item: item.into(),
+            asset: None,
+            error: error.into(),
+        }
+    }
+
+    /// Narrows the failure down to one of the city's images
+    pub fn with_asset(mut self, asset: AssetKind) -> Self {
+        self.asset = Some(asset);
+        self
+    }
+}
+
+/// An item that used manual overrides instead of what the scraper would have found
+#[derive(Clone, Debug, Serialize)]
+pub struct OverrideUse {
+    /// File stem of the city
+    pub item: String,
+    /// Which parts were overridden: `article`, `coa` or `background`
+    pub fields: Vec<String>,
+}
+
+impl std::fmt::Display for OverrideUse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.item, self.fields.join(", "))
+    }
+}
+
+/// What a stage did, adding reports up gives the totals of a run
 #[must_use]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, Serialize)]
 pub struct ReturnReport {
+    /// Name of the stage, failures are grouped by it, see [`crate::failures::FailureLog`]
     pub job_name: String,
-    pub duration: std::time::Duration,
+    /// Wall-clock time the stage took
+    #[serde(rename = "duration_secs", serialize_with = "serialize_secs")]
+    pub duration: Duration,
+    /// Items processed successfully
     pub amount_ok: usize,
+    /// Items that failed, only some of them have a [`Failure`] with the details
     pub amount_err: usize,
+    /// Details of the failed items
+    pub failures: Vec<Failure>,
+    /// Cities that used overrides, see [`crate::Paths::overrides`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<OverrideUse>,
+    /// Bytes fetched from the network, images read from overrides don't count
+    #[serde(skip_serializing_if = "is_zero")]
+    pub bytes_downloaded: u64,
+}
+
+fn is_zero(bytes: &u64) -> bool {
+    *bytes == 0
+}
+
+impl ReturnReport {
+    /// Share of processed items that failed, 0.0 when nothing was processed
+    pub fn error_rate(&self) -> f64 {
+        let total = self.amount_ok + self.amount_err;
+        if total == 0 {
+            0.0
+        } else {
+            self.amount_err as f64 / total as f64
+        }
+    }
 }
 
 impl std::fmt::Display for ReturnReport {
@@ -72,31 +465,49 @@ impl std::fmt::Display for ReturnReport {
             self.amount_err,
             LogStyle::Clear,
             if self.amount_err == 1 { "" } else { "s" }
-        )
+        )?;
+
+        if self.bytes_downloaded > 0 {
+            write!(
+                f,
+                ", {}{:.1} MiB{} downloaded",
+                LogStyle::Cyan,
+                self.bytes_downloaded as f64 / (1024.0 * 1024.0),
+                LogStyle::Clear
+            )?;
+        }
+
+        Ok(())
     }
 }
 
 impl std::ops::Add for ReturnReport {
     type Output = Self;
 
-    fn add(self, rhs: Self) -> Self::Output {
+    fn add(mut self, mut rhs: Self) -> Self::Output {
+        self.failures.append(&mut rhs.failures);
+        self.overrides.append(&mut rhs.overrides);
         Self {
             job_name: "TOTAL".into(),
             duration: self.duration + rhs.duration,
             amount_ok: self.amount_ok + rhs.amount_ok,
             amount_err: self.amount_err + rhs.amount_err,
+            failures: self.failures,
+            overrides: self.overrides,
+            bytes_downloaded: self.bytes_downloaded + rhs.bytes_downloaded,
         }
     }
 }
 
-pub fn ensure_exists(path: &Path) -> AppResult<()> {
+#[cfg(any(feature = "scrape", feature = "render"))]
+pub(crate) fn ensure_exists(path: &Path) -> AppResult<()> {
     if !path.exists() {
-        fs::create_dir_all(path)?;
+        std::fs::create_dir_all(path)?;
     }
     Ok(())
 }
 
-pub fn format_file_name_parts(city_identifier: &str, city_name: &str) -> String {
+pub(crate) fn format_file_name_parts(city_identifier: &str, city_name: &str) -> String {
     format!(
         "{}+{}",
         city_identifier.replace(' ', "_"),
@@ -104,15 +515,16 @@ pub fn format_file_name_parts(city_identifier: &str, city_name: &str) -> String
     )
 }
 
-pub fn format_file_name(city: &City) -> String {
+pub(crate) fn format_file_name(city: &City) -> String {
     format_file_name_parts(&city.identifier, &city.name)
 }
 
-pub fn file_stem(path: &std::path::Path) -> Option<String> {
+pub(crate) fn file_stem(path: &std::path::Path) -> Option<String> {
     Some(path.file_stem()?.to_str()?.to_owned())
 }
 
-pub fn capitalize(s: &str) -> String {
+#[cfg(feature = "render")]
+pub(crate) fn capitalize(s: &str) -> String {
     let mut c = s.chars();
     match c.next() {
         None => String::new(),
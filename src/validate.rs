@@ -0,0 +1,181 @@
+use crate::{
+    manifest::unix_time,
+    utils::{AppResult, Context, Reason, ensure_exists},
+};
+use std::{
+    fs, panic,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// How much of the start of a file is looked at to tell an SVG from other XML and HTML
+const SNIFF_LEN: usize = 1024;
+
+/// How much of the end of a JPEG is searched for its end marker, cameras and editors append
+/// their own trailers after it
+const JPEG_TAIL_LEN: usize = 64 * 1024;
+
+/// Formats the image editor can open
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Svg,
+}
+
+impl ImageFormat {
+    /// Extension files of this format are saved with, whatever the server called them
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Webp => "webp",
+            ImageFormat::Svg => "svg",
+        }
+    }
+
+    /// Tells the format by the magic number, SVGs by their root element
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(ImageFormat::Jpeg)
+        } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            Some(ImageFormat::Webp)
+        } else if is_svg(bytes) {
+            Some(ImageFormat::Svg)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the file ends the way the format says it does, a cut off download doesn't
+    fn is_whole(self, bytes: &[u8]) -> bool {
+        match self {
+            // the IEND chunk has no data, only its length, type and CRC
+            ImageFormat::Png => bytes.len() >= 12 && bytes[..bytes.len() - 4].ends_with(b"IEND"),
+            // within the scan data 0xff is always followed by 0 or a restart marker
+            ImageFormat::Jpeg => bytes[bytes.len().saturating_sub(JPEG_TAIL_LEN)..]
+                .windows(2)
+                .any(|pair| pair == [0xff, 0xd9]),
+            // the RIFF header gives the size of everything after the first 8 bytes
+            ImageFormat::Webp => {
+                let size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+                bytes.len() as u64 >= size as u64 + 8
+            }
+            ImageFormat::Svg => String::from_utf8_lossy(bytes).trim_end().ends_with('>'),
+        }
+    }
+}
+
+/// XML with an `svg` root, error pages are HTML that may have inline SVGs in them
+fn is_svg(bytes: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(SNIFF_LEN)]).to_lowercase();
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    head.starts_with('<') && head.contains("<svg") && !head.contains("<html")
+}
+
+/// Tries decoding the image, which is only possible with the `render` feature
+#[cfg(feature = "render")]
+fn decode(bytes: &[u8], format: ImageFormat) -> Result<(), String> {
+    use image::ImageFormat as Raster;
+
+    let raster = match format {
+        ImageFormat::Svg => {
+            return resvg::usvg::Tree::from_data(bytes, &resvg::usvg::Options::default())
+                .map(drop)
+                .map_err(|err| err.to_string());
+        }
+        ImageFormat::Png => Raster::Png,
+        ImageFormat::Jpeg => Raster::Jpeg,
+        ImageFormat::Webp => Raster::WebP,
+    };
+
+    image::load_from_memory_with_format(bytes, raster)
+        .map(drop)
+        .map_err(|err| err.to_string())
+}
+
+/// Checks that `bytes` are an image of a known format that isn't cut off, without decoding it.
+/// Cheap enough for every file saved by earlier runs
+pub fn check_whole(bytes: &[u8]) -> Result<ImageFormat, Reason> {
+    let format = ImageFormat::sniff(bytes)
+        .ok_or_else(|| Reason::CorruptImage("not a PNG, JPEG, WebP or SVG file".into()))?;
+    if !format.is_whole(bytes) {
+        return Err(Reason::CorruptImage(format!(
+            "{} file is cut off",
+            format.extension()
+        )));
+    }
+    Ok(format)
+}
+
+/// Checks that `bytes` are a whole image the editor can open, returning its format
+pub fn validate(bytes: &[u8]) -> Result<ImageFormat, Reason> {
+    let format = check_whole(bytes)?;
+
+    #[cfg(feature = "render")]
+    decode(bytes, format).map_err(Reason::CorruptImage)?;
+
+    Ok(format)
+}
+
+/// [`validate`] on the blocking thread pool, decoding a large image would hold up the tasks
+/// sharing its worker. The bytes are handed back along with the result
+pub async fn validate_blocking(bytes: Vec<u8>) -> (Vec<u8>, Result<ImageFormat, Reason>) {
+    let validated = tokio::task::spawn_blocking(move || {
+        let result = validate(&bytes);
+        (bytes, result)
+    })
+    .await;
+
+    match validated {
+        Ok(validated) => validated,
+        // a panicking decoder is a bug, it shouldn't look like a corrupt image
+        Err(err) => panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// Moves a file that failed validation to `quarantine` to be looked at, out of the way of
+/// the stages that would take it for a good one
+pub fn quarantine(quarantine: &Path, path: &Path) -> AppResult<PathBuf> {
+    ensure_exists(quarantine).path(quarantine)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let target = quarantine.join(format!("{}_{name}", unix_time()));
+    fs::rename(path, &target).path(path)?;
+    Ok(target)
+}
+
+/// Keeps downloaded `bytes` that failed validation in `quarantine` as `name`
+pub fn quarantine_bytes(quarantine: &Path, name: &str, bytes: &[u8]) -> AppResult<PathBuf> {
+    ensure_exists(quarantine).path(quarantine)?;
+    let target = quarantine.join(format!("{}_{name}", unix_time()));
+    fs::write(&target, bytes).path(&target)?;
+    Ok(target)
+}
+
+/// Whether `path` is a temporary file [`write_atomic`] left behind when a run was interrupted
+pub fn is_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.') && name.ends_with(".part"))
+}
+
+/// Writes `bytes` next to `path` first and renames them into place,
+/// so that a crash never leaves a partial file behind under the real name
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> AppResult<()> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    // hidden, so that its stem doesn't pass for the stem of a city, and numbered, so that
+    // two writes of the same file don't take each other's temporary file away
+    let temp = path.with_file_name(format!(
+        ".{name}.{}-{}.part",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temp, bytes).path(&temp)?;
+    fs::rename(&temp, path).path(path)?;
+    Ok(())
+}
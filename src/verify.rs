@@ -0,0 +1,256 @@
+use crate::{
+    parser::City,
+    utils::{AppResult, AssetKind},
+    wiki::Infobox,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// Points for an infobox TERYT code equal to the city's one, enough to accept the page alone.
+/// A different code takes as many away, it's a different unit with the same name
+pub const TERYT_POINTS: i32 = 10;
+
+/// Largest ratio of two populations that still counts as the same order of magnitude,
+/// half of one either way
+const POPULATION_RATIO: f64 = 3.162;
+
+/// How well the infobox of an article matches a dataset row
+#[derive(Debug, Default)]
+pub struct Verdict {
+    pub score: i32,
+    /// Every check that could be made, e.g. `powiat ok` or `population 120000 != 5936`
+    pub checks: Vec<String>,
+}
+
+/// `226401 1` and `2264011` are the same code
+pub fn teryt_digits(value: &str) -> String {
+    value.chars().filter(char::is_ascii_digit).collect()
+}
+
+/// First number in `value`, reading `5 936`, `5&nbsp;936` and `{{formatnum:5936}}` alike
+fn parse_number(value: &str) -> Option<u64> {
+    let value = value.replace("&nbsp;", " ");
+    let start = value.find(|c: char| c.is_ascii_digit())?;
+    let digits: String = value[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || matches!(c, ' ' | '\u{a0}' | '.'))
+        .filter(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+impl Verdict {
+    fn check(&mut self, name: &str, passed: bool, detail: String) {
+        if passed {
+            self.score += 1;
+            self.checks.push(format!("{name} ok"));
+        } else {
+            self.score -= 1;
+            self.checks.push(format!("{name} {detail}"));
+        }
+    }
+
+    /// Compares TERYT, voivodeship, powiat and population, the ones the infobox has
+    pub fn new(city: &City, infobox: &Infobox) -> Self {
+        let mut verdict = Self::default();
+
+        if let Some(code) = infobox.get("TERC").map(teryt_digits)
+            && !code.is_empty()
+        {
+            let expected = teryt_digits(&city.identifier);
+            if code == expected {
+                verdict.score += TERYT_POINTS;
+                verdict.checks.push("TERYT ok".into());
+            } else {
+                verdict.score -= TERYT_POINTS;
+                verdict.checks.push(format!("TERYT {code} != {expected}"));
+            }
+        }
+
+        if let Some(value) = infobox.get("województwo") {
+            let value = value.to_lowercase();
+            let expected = city.voivodeship.to_lowercase();
+            verdict.check(
+                "voivodeship",
+                value.contains(&expected),
+                format!("{value} != {expected}"),
+            );
+        }
+
+        if let Some(value) = infobox.get("powiat") {
+            let value = value.to_lowercase();
+            let expected = city.powiat.to_lowercase();
+            // cities with powiat rights are their own powiat in the dataset
+            let own_powiat =
+                expected == city.name.to_lowercase() && value.contains("na prawach powiatu");
+            verdict.check(
+                "powiat",
+                own_powiat || value.contains(&expected),
+                format!("{value} != {expected}"),
+            );
+        }
+
+        if let Some(population) = infobox.get("liczba ludności").and_then(parse_number) {
+            let expected = city.total_population;
+            let ratio = population.max(expected) as f64 / population.min(expected).max(1) as f64;
+            verdict.check(
+                "population",
+                ratio <= POPULATION_RATIO,
+                format!("{population} != {expected}"),
+            );
+        }
+
+        verdict
+    }
+}
+
+/// A candidate article that was turned down
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rejected {
+    pub title: String,
+    pub reason: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<i32>,
+}
+
+/// Where one asset of a city was found
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AssetMatch {
+    /// `plwiki`, `commons`, `override` or `none` when nothing had it
+    pub source: String,
+    /// Article, Commons category or, for overrides, the city name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// What the scraper settled on for one city
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MatchRecord {
+    /// Article or Wikidata item the images came from, none when nothing matched
+    pub title: Option<String>,
+    /// Source that gave the images, e.g. `plwiki` or `commons`, `override` or `none`
+    pub source: String,
+    pub score: Option<i32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rejected: Vec<Rejected>,
+    /// Where every asset that was asked for came from
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub assets: BTreeMap<AssetKind, AssetMatch>,
+}
+
+impl MatchRecord {
+    pub fn reject(&mut self, title: &str, reason: impl Into<String>, score: Option<i32>) {
+        self.rejected.push(Rejected {
+            title: title.to_owned(),
+            reason: reason.into(),
+            score,
+        });
+    }
+
+    /// Takes `assets` from `title`, the first accepted source is the one the record names
+    pub fn accept(
+        &mut self,
+        title: &str,
+        source: &str,
+        verdict: Option<Verdict>,
+        assets: &[AssetKind],
+    ) {
+        for &asset in assets {
+            let found = AssetMatch {
+                source: source.to_owned(),
+                title: Some(title.to_owned()),
+            };
+            self.assets.insert(asset, found);
+        }
+        if self.title.is_some() {
+            return;
+        }
+
+        self.title = Some(title.to_owned());
+        self.source = source.to_owned();
+        if let Some(verdict) = verdict {
+            self.score = Some(verdict.score);
+            self.checks = verdict.checks;
+        }
+    }
+
+    /// Marks the assets no source had
+    pub fn missing(&mut self, assets: &[AssetKind]) {
+        for &asset in assets {
+            let missing = AssetMatch {
+                source: "none".into(),
+                title: None,
+            };
+            self.assets.insert(asset, missing);
+        }
+    }
+}
+
+/// Matches of every scraped city, kept for review between runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MatchLog {
+    pub cities: BTreeMap<String, MatchRecord>,
+}
+
+impl MatchLog {
+    /// Loads the matches file, an absent file means nothing was scraped yet
+    pub fn load(path: &Path) -> AppResult<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> AppResult<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{facts::Facts, wiki::infobox};
+
+    fn city(name: &str, powiat: &str) -> City {
+        City {
+            identifier: "126101 1".into(),
+            name: name.into(),
+            powiat: powiat.into(),
+            area_ha: 32685,
+            area_km: 327,
+            total_population: 800653,
+            population_per_km: 2449,
+            voivodeship: "małopolskie".into(),
+            facts: Facts::default(),
+        }
+    }
+
+    #[test]
+    fn city_with_powiat_rights_is_its_own_powiat() {
+        let infobox = infobox(
+            "{{Miasto infobox\n| województwo = [[województwo małopolskie|małopolskie]]\n\
+             | powiat = miasto na prawach powiatu\n| liczba ludności = 800 653\n}}",
+        );
+
+        let verdict = Verdict::new(&city("Kraków", "kraków"), &infobox);
+
+        assert_eq!(
+            verdict.checks,
+            ["voivodeship ok", "powiat ok", "population ok"]
+        );
+        assert_eq!(verdict.score, 3);
+    }
+
+    #[test]
+    fn powiat_rights_of_another_city_dont_count() {
+        let infobox = infobox("| powiat = miasto na prawach powiatu");
+
+        let verdict = Verdict::new(&city("Skawina", "krakowski"), &infobox);
+
+        assert_eq!(verdict.score, -1);
+    }
+}
@@ -0,0 +1,351 @@
+//! Re-renders slides as their inputs change, see [`watch`].
+
+use crate::{
+    config::Config,
+    credits::CREDIT_SUFFIX,
+    facts::FactLog,
+    failures::FailureLog,
+    image_editor::process_assets,
+    log,
+    logger::LogStyle,
+    parser::{VOIVODESHIP_COUNT, Voivodeship, parse_csv},
+    paths::Paths,
+    slides_gen::{generate_slides, generate_voivodeship_slides},
+    utils::{AppError, AppResult, Context, Stage, ensure_exists, file_stem, format_file_name},
+};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// What has to be rebuilt after a batch of file changes
+#[derive(Default, Debug)]
+struct Rebuild {
+    dataset: bool,
+    config: bool,
+    facts: bool,
+    all_slides: bool,
+    voivodeships: BTreeSet<String>,
+    /// file stems of cities whose source images changed, with the edited file to drop
+    assets: BTreeSet<(String, PathBuf)>,
+}
+
+impl Rebuild {
+    fn is_empty(&self) -> bool {
+        !self.dataset
+            && !self.config
+            && !self.facts
+            && !self.all_slides
+            && self.voivodeships.is_empty()
+            && self.assets.is_empty()
+    }
+
+    fn add(&mut self, paths: &Paths, path: &Path) {
+        let parent = path.parent();
+        let Some(stem) = file_stem(path) else {
+            return;
+        };
+
+        if path == paths.dataset {
+            self.dataset = true;
+        } else if path == paths.config {
+            self.config = true;
+        } else if path == paths.facts {
+            self.facts = true;
+        } else if path == paths.data.join("credits.txt")
+            || path.to_string_lossy().ends_with(CREDIT_SUFFIX)
+            || parent == Some(&paths.fonts)
+            || parent == Some(&paths.icons)
+        {
+            self.all_slides = true;
+        } else if parent == Some(&paths.maps) {
+            let name = stem.trim_end_matches("_transparent").to_owned();
+            self.voivodeships.insert(name);
+        } else if parent == Some(&paths.backgrounds) {
+            let edited = paths.edited_backgrounds.join(format!("{stem}.webp"));
+            self.assets.insert((stem, edited));
+        } else if parent == Some(&paths.coas) {
+            // the image editor converts SVGs to PNGs next to them, reacting to that would loop
+            if path.extension().is_some_and(|ext| ext == "png")
+                && paths.coas.join(format!("{stem}.svg")).exists()
+            {
+                return;
+            }
+            let edited = paths.edited_coas.join(format!("{stem}.webp"));
+            self.assets.insert((stem, edited));
+        }
+    }
+}
+
+/// `path` with symlinks and `..` resolved and made absolute, the way event paths come.
+/// A file that doesn't exist yet gets its directory resolved instead
+fn canonical(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => parent
+            .canonicalize()
+            .map_or_else(|_| path.to_owned(), |parent| parent.join(name)),
+        _ => path.to_owned(),
+    }
+}
+
+/// The inputs events are compared with, resolved with [`canonical`]
+fn watched_paths(paths: &Paths) -> Paths {
+    let mut watched = paths.clone();
+    for path in [
+        &mut watched.dataset,
+        &mut watched.config,
+        &mut watched.facts,
+        &mut watched.data,
+        &mut watched.fonts,
+        &mut watched.icons,
+        &mut watched.maps,
+        &mut watched.backgrounds,
+        &mut watched.coas,
+    ] {
+        *path = canonical(path);
+    }
+    watched
+}
+
+fn voivodeship_of(dataset: &[Voivodeship], stem: &str) -> Option<usize> {
+    dataset.iter().position(|voivodeship| {
+        voivodeship
+            .content
+            .iter()
+            .any(|city| format_file_name(city) == stem)
+    })
+}
+
+async fn rebuild(
+    paths: &Paths,
+    config: &mut Config,
+    dataset: &mut [Voivodeship; VOIVODESHIP_COUNT],
+    failures: &mut FailureLog,
+    offline: bool,
+    plan: Rebuild,
+) -> AppResult<()> {
+    if plan.config {
+        *config = Config::load(&paths.config)
+            .path(&paths.config)
+            .stage(Stage::Setup)?;
+        // `--offline` has to outlive the reload, it isn't in the file
+        config.http.offline |= offline;
+        log!(
+            [LogStyle::Cyan],
+            "REBUILT",
+            "Reloaded config {:?}",
+            paths.config
+        );
+    }
+
+    if plan.dataset {
+        *dataset = parse_csv(&paths.dataset)?;
+        FactLog::load(&paths.facts)
+            .path(&paths.facts)?
+            .apply(dataset);
+
+        // cities new to the dataset get their images from the next scrape, not from watching
+        let (background_report, coa_report) =
+            process_assets(paths, dataset, None, config.concurrency.image_editor)
+                .await
+                .stage(Stage::ImageEditor)?;
+        failures.record(&background_report);
+        failures.record(&coa_report);
+        failures.save(&paths.failures)?;
+
+        let report = generate_slides(paths, dataset, &config.slides).stage(Stage::SlidesGen)?;
+        log!(
+            [LogStyle::Cyan],
+            "REBUILT",
+            "Dataset changed, edited images the new cities have and rendered all slides: {report}"
+        );
+        return Ok(());
+    }
+
+    if plan.facts {
+        FactLog::load(&paths.facts)
+            .path(&paths.facts)?
+            .apply(dataset);
+        log!(
+            [LogStyle::Cyan],
+            "REBUILT",
+            "Reloaded facts {:?}",
+            paths.facts
+        );
+    }
+
+    let mut voivodeships = BTreeSet::new();
+    for name in &plan.voivodeships {
+        if let Some(idx) = dataset.iter().position(|v| &v.name == name) {
+            voivodeships.insert(idx);
+        }
+    }
+
+    if !plan.assets.is_empty() {
+        for (stem, edited) in &plan.assets {
+            match std::fs::remove_file(edited) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    return Err(AppError::from(err).with_path(edited).with_city(stem));
+                }
+                _ => {}
+            }
+
+            if let Some(idx) = voivodeship_of(dataset, stem) {
+                voivodeships.insert(idx);
+            }
+        }
+
+        let (background_report, coa_report) =
+            process_assets(paths, dataset, None, config.concurrency.image_editor)
+                .await
+                .stage(Stage::ImageEditor)?;
+        failures.record(&background_report);
+        failures.record(&coa_report);
+        failures.save(&paths.failures)?;
+
+        log!(
+            [LogStyle::Cyan],
+            "REBUILT",
+            "Edited images of {}",
+            plan.assets
+                .iter()
+                .map(|(stem, _)| stem.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    if plan.all_slides || plan.config || plan.facts {
+        let report = generate_slides(paths, dataset, &config.slides).stage(Stage::SlidesGen)?;
+        log!([LogStyle::Cyan], "REBUILT", "All slides: {report}");
+        return Ok(());
+    }
+
+    for idx in voivodeships {
+        let report = generate_voivodeship_slides(paths, dataset, idx, &config.slides)
+            .stage(Stage::SlidesGen)?;
+        log!(
+            [LogStyle::Cyan],
+            "REBUILT",
+            "Slides of woj. {}: {report}",
+            dataset[idx].name
+        );
+    }
+
+    Ok(())
+}
+
+/// Watches the inputs of the pipeline and re-runs only the stages affected by a change,
+/// `offline` is the `--offline` flag, kept on whatever a reloaded config says
+pub async fn watch(
+    paths: &Paths,
+    mut config: Config,
+    mut dataset: [Voivodeship; VOIVODESHIP_COUNT],
+    mut failures: FailureLog,
+    offline: bool,
+) -> AppResult<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        // the receiver only goes away when watching stops
+        let _ = tx.send(event);
+    })
+    .map_err(io::Error::other)?;
+
+    for directory in [
+        &paths.fonts,
+        &paths.icons,
+        &paths.maps,
+        &paths.backgrounds,
+        &paths.coas,
+        &paths.data,
+    ] {
+        ensure_exists(directory)?;
+    }
+
+    // `--config` and the other paths may be relative or outside the data directory
+    let watched = watched_paths(paths);
+    let directories = BTreeSet::from([
+        watched.fonts.clone(),
+        watched.icons.clone(),
+        watched.maps.clone(),
+        watched.backgrounds.clone(),
+        watched.coas.clone(),
+        watched.data.clone(),
+        canonical(watched.config.parent().unwrap_or(Path::new("."))),
+        canonical(watched.dataset.parent().unwrap_or(Path::new("."))),
+        canonical(watched.facts.parent().unwrap_or(Path::new("."))),
+    ]);
+    // a config file in a directory that doesn't exist is never there to change
+    for directory in directories.iter().filter(|directory| directory.is_dir()) {
+        watcher
+            .watch(directory, RecursiveMode::NonRecursive)
+            .map_err(io::Error::other)
+            .path(directory)?;
+    }
+
+    log!(
+        [LogStyle::Blue, LogStyle::Bold],
+        "WATCH",
+        "Watching for changes, press Ctrl+C to stop"
+    );
+
+    while let Some(event) = rx.recv().await {
+        let mut plan = Rebuild::default();
+        let mut next = Some(event);
+
+        // collect everything that changes in a short burst, editors tend to write files in steps
+        while let Some(event) = next {
+            match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                    for path in &event.paths {
+                        plan.add(&watched, path);
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => log!(Warn: [LogStyle::Yellow], "WATCH", "{err}"),
+            }
+
+            next = tokio::time::timeout(DEBOUNCE, rx.recv())
+                .await
+                .ok()
+                .flatten();
+        }
+
+        if plan.is_empty() {
+            continue;
+        }
+
+        log!(Debug: [LogStyle::Blue], "WATCH", "Changes: {plan:?}");
+
+        // changes made while rebuilding wait in the channel for the next round
+        if let Err(err) = rebuild(
+            paths,
+            &mut config,
+            &mut dataset,
+            &mut failures,
+            offline,
+            plan,
+        )
+        .await
+        {
+            log!(
+                Error: [LogStyle::Bold, LogStyle::Red],
+                "CRITICAL ERROR",
+                "{err}"
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,586 @@
+use crate::{
+    credits::Credit,
+    http::Http,
+    utils::{AppResult, Context, Reason},
+};
+use serde::{Deserialize, de::DeserializeOwned};
+use std::{collections::HashMap, sync::Arc};
+
+/// Namespace prefixes a file can be linked with in Polish, English and German wikitext
+const FILE_PREFIXES: [&str; 6] = ["Plik:", "File:", "Grafika:", "Image:", "Datei:", "Bild:"];
+
+#[derive(Deserialize)]
+struct ApiError {
+    code: String,
+    info: String,
+}
+
+/// Any response, an error comes back with status 200 whatever was asked for
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: Option<ApiError>,
+}
+
+#[derive(Deserialize)]
+struct QueryResponse {
+    #[serde(default)]
+    query: Query,
+    /// Parameters asking for the rest, when the answer didn't fit into one response
+    #[serde(default, rename = "continue")]
+    next: Option<HashMap<String, serde_json::Value>>,
+}
+
+#[derive(Default, Deserialize)]
+struct Query {
+    #[serde(default)]
+    redirects: Vec<Redirect>,
+    #[serde(default)]
+    pages: Vec<Page>,
+    #[serde(default)]
+    search: Vec<SearchHit>,
+}
+
+#[derive(Deserialize)]
+struct SearchHit {
+    title: String,
+}
+
+#[derive(Deserialize)]
+struct Redirect {
+    from: String,
+}
+
+#[derive(Deserialize)]
+struct Page {
+    title: String,
+    #[serde(default)]
+    missing: bool,
+    #[serde(default)]
+    invalid: bool,
+    #[serde(default)]
+    pageprops: PageProps,
+    pageimage: Option<String>,
+    /// Plain text of the lead section, only there when asked for
+    extract: Option<String>,
+    #[serde(default)]
+    imageinfo: Vec<ImageInfo>,
+}
+
+#[derive(Default, Deserialize)]
+struct PageProps {
+    disambiguation: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ImageInfo {
+    url: String,
+    descriptionurl: String,
+    /// Only there when a thumbnail width was asked for
+    thumburl: Option<String>,
+    thumbwidth: Option<u32>,
+    #[serde(default)]
+    width: u32,
+    #[serde(default)]
+    height: u32,
+    #[serde(default)]
+    mime: String,
+    #[serde(default)]
+    extmetadata: HashMap<String, MetadataValue>,
+}
+
+#[derive(Deserialize)]
+struct MetadataValue {
+    value: serde_json::Value,
+}
+
+impl ImageInfo {
+    /// Text of an extmetadata field, which comes as HTML
+    fn metadata(&self, key: &str) -> Option<String> {
+        let text = strip_html(self.extmetadata.get(key)?.value.as_str()?);
+        (!text.is_empty()).then_some(text)
+    }
+
+    /// `width` is the thumbnail width that was asked for, if any
+    fn into_file_info(self, file: String, width: Option<u32>) -> FileInfo {
+        FileInfo {
+            credit: Credit {
+                source: self.descriptionurl.clone(),
+                author: self.metadata("Artist"),
+                licence: self.metadata("LicenseShortName"),
+                licence_url: self.metadata("LicenseUrl"),
+                found_on: None,
+            },
+            url: match (self.thumburl, self.thumbwidth, width) {
+                (Some(thumb), Some(thumb_width), Some(width)) if thumb_width >= width => thumb,
+                _ => self.url,
+            },
+            file,
+            width: self.width,
+            height: self.height,
+            mime: self.mime,
+        }
+    }
+}
+
+/// Where to download a file from and who it's credited to
+#[derive(Clone, Debug)]
+pub struct FileInfo {
+    /// Name without the namespace
+    pub file: String,
+    pub url: String,
+    /// Size of the original upload
+    pub width: u32,
+    pub height: u32,
+    pub mime: String,
+    pub credit: Credit,
+}
+
+/// Files a generator lists per request, the most `iiurlwidth` scales at once
+const GENERATOR_LIMIT: &str = "50";
+
+/// Query parameters asking for the URL, size and credit of files, thumbnails when `width` is set
+fn image_info_params(width: Option<&str>) -> Vec<(&'static str, &str)> {
+    let mut params = vec![
+        ("prop", "imageinfo"),
+        ("iiprop", "url|size|mime|extmetadata"),
+        ("iiextmetadatafilter", "Artist|LicenseShortName|LicenseUrl"),
+    ];
+    if let Some(width) = width {
+        params.push(("iiurlwidth", width));
+    }
+    params
+}
+
+#[derive(Deserialize)]
+struct ParseResponse {
+    parse: Option<Parsed>,
+}
+
+#[derive(Deserialize)]
+struct Parsed {
+    wikitext: String,
+}
+
+#[derive(Deserialize)]
+struct EntitiesResponse {
+    #[serde(default)]
+    entities: HashMap<String, Entity>,
+}
+
+#[derive(Deserialize)]
+struct Entity {
+    #[serde(default)]
+    claims: HashMap<String, Vec<Claim>>,
+    #[serde(default)]
+    sitelinks: HashMap<String, Sitelink>,
+}
+
+#[derive(Deserialize)]
+struct Claim {
+    rank: String,
+    mainsnak: Snak,
+}
+
+#[derive(Deserialize)]
+struct Snak {
+    datavalue: Option<DataValue>,
+}
+
+#[derive(Deserialize)]
+struct DataValue {
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct Sitelink {
+    title: String,
+}
+
+impl Entity {
+    /// Text value of the preferred statement of `property`, or of the first normal one
+    fn string_claim(&self, property: &str) -> Option<String> {
+        let claims = self.claims.get(property)?;
+        claims
+            .iter()
+            .find(|claim| claim.rank == "preferred")
+            .or_else(|| claims.iter().find(|claim| claim.rank == "normal"))?
+            .mainsnak
+            .datavalue
+            .as_ref()?
+            .value
+            .as_str()
+            .map(ToOwned::to_owned)
+    }
+}
+
+/// The parts of a Wikidata item the scraper cares about, file names are without the namespace
+#[derive(Debug)]
+pub struct WikidataItem {
+    pub id: String,
+    /// Titles of the articles about the item by wiki, only the requested wikis are there
+    pub articles: HashMap<String, String>,
+    /// Coat of arms image (P94)
+    pub coat_of_arms: Option<String>,
+    /// Image (P18)
+    pub image: Option<String>,
+    /// Commons category (P373), without the namespace
+    pub commons_category: Option<String>,
+}
+
+/// An existing article, after following redirects
+pub struct Article {
+    pub title: String,
+    /// Title that was asked for when it redirected here
+    pub redirected_from: Option<String>,
+    /// File the wiki picked as the lead image of the article, without the namespace
+    pub page_image: Option<String>,
+}
+
+pub enum PageLookup {
+    Found(Article),
+    Missing,
+    Disambiguation,
+}
+
+/// Parameters of the first infobox of an article, as written in wikitext
+#[derive(Debug, Default)]
+pub struct Infobox {
+    params: HashMap<String, String>,
+}
+
+impl Infobox {
+    /// Raw value of `key`, comments and references cut off
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.params.get(key).map(String::as_str)
+    }
+
+    /// File in the first of `keys` that holds one, without the namespace
+    pub fn file(&self, keys: &[String]) -> Option<String> {
+        keys.iter().find_map(|key| file_name(self.get(key)?))
+    }
+}
+
+/// Client of the MediaWiki action API
+pub struct WikiApi {
+    http: Arc<Http>,
+    api_url: String,
+}
+
+fn check(error: Option<ApiError>) -> AppResult<()> {
+    match error {
+        Some(error) => Err(Reason::WikiApi(format!("{}: {}", error.code, error.info)).into()),
+        None => Ok(()),
+    }
+}
+
+/// Drops the tags of an HTML snippet and decodes the few entities authors tend to contain
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&#039;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Turns an infobox value like `[[Plik:POL Kraków COA.svg|100px]]` into `POL Kraków COA.svg`
+fn file_name(value: &str) -> Option<String> {
+    let mut value = value.trim_start_matches("[[");
+
+    for prefix in FILE_PREFIXES {
+        if let Some(stripped) = value.strip_prefix(prefix) {
+            value = stripped;
+            break;
+        }
+    }
+
+    let value = value.split(['|', ']']).next().unwrap_or_default().trim();
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+/// Reads the `| key = value` lines of the first infobox in `wikitext`,
+/// the first occurrence of a key wins
+pub fn infobox(wikitext: &str) -> Infobox {
+    let mut params = HashMap::new();
+
+    for line in wikitext.lines() {
+        let Some((key, value)) = line
+            .trim()
+            .strip_prefix('|')
+            .and_then(|x| x.split_once('='))
+        else {
+            continue;
+        };
+
+        let value = ["<!--", "<ref"]
+            .iter()
+            .filter_map(|cut| value.find(cut))
+            .min()
+            .map_or(value, |end| &value[..end])
+            .trim();
+        if !value.is_empty() {
+            params
+                .entry(key.trim().to_owned())
+                .or_insert_with(|| value.to_owned());
+        }
+    }
+
+    Infobox { params }
+}
+
+impl WikiApi {
+    pub fn new(http: Arc<Http>, api_url: impl Into<String>) -> Self {
+        Self {
+            http,
+            api_url: api_url.into(),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, params: &[(&str, &str)]) -> AppResult<T> {
+        let params = [("format", "json"), ("formatversion", "2")]
+            .iter()
+            .chain(params);
+        let url = reqwest::Url::parse_with_params(&self.api_url, params)
+            .map_err(std::io::Error::other)
+            .url(&self.api_url)?;
+
+        let text = self.http.get_text(url.as_str()).await?;
+        let response: ErrorResponse = serde_json::from_str(&text).url(url.as_str())?;
+        if response.error.is_some() {
+            // a rate limit or a lag error must not be answered from the cache next time
+            self.http.forget(url.as_str())?;
+        }
+        check(response.error)?;
+
+        serde_json::from_str(&text).url(url.as_str())
+    }
+
+    /// Looks `title` up, following redirects
+    pub async fn page(&self, title: &str) -> AppResult<PageLookup> {
+        let response: QueryResponse = self
+            .get(&[
+                ("action", "query"),
+                ("titles", title),
+                ("redirects", "1"),
+                ("prop", "pageprops|pageimages"),
+                ("ppprop", "disambiguation"),
+                ("piprop", "name"),
+            ])
+            .await?;
+
+        let Some(page) = response.query.pages.into_iter().next() else {
+            return Ok(PageLookup::Missing);
+        };
+
+        if page.missing || page.invalid {
+            return Ok(PageLookup::Missing);
+        }
+
+        if page.pageprops.disambiguation.is_some() {
+            return Ok(PageLookup::Disambiguation);
+        }
+
+        Ok(PageLookup::Found(Article {
+            title: page.title,
+            redirected_from: response.query.redirects.into_iter().next().map(|x| x.from),
+            page_image: page.pageimage,
+        }))
+    }
+
+    /// Infobox of the article, empty when it has none
+    pub async fn infobox(&self, title: &str) -> AppResult<Infobox> {
+        let response: ParseResponse = self
+            .get(&[
+                ("action", "parse"),
+                ("page", title),
+                ("prop", "wikitext"),
+                ("section", "0"),
+            ])
+            .await?;
+
+        Ok(response
+            .parse
+            .map(|parsed| infobox(&parsed.wikitext))
+            .unwrap_or_default())
+    }
+
+    /// Plain text of the lead section of the article, `None` when it has none
+    pub async fn lead(&self, title: &str) -> AppResult<Option<String>> {
+        let response: QueryResponse = self
+            .get(&[
+                ("action", "query"),
+                ("titles", title),
+                ("redirects", "1"),
+                ("prop", "extracts"),
+                ("exintro", "1"),
+                ("explaintext", "1"),
+            ])
+            .await?;
+
+        Ok(response
+            .query
+            .pages
+            .into_iter()
+            .find_map(|page| page.extract)
+            .filter(|extract| !extract.trim().is_empty()))
+    }
+
+    /// URL of `file` with its size, author and licence, `None` when there's no such file.
+    /// With a `width` it's a thumbnail that wide, or the original upload when that's narrower
+    pub async fn file_info(&self, file: &str, width: Option<u32>) -> AppResult<Option<FileInfo>> {
+        let title = format!("{}{file}", FILE_PREFIXES[1]);
+        let width_param = width.map(|width| width.to_string());
+        let mut params = vec![("action", "query"), ("titles", title.as_str())];
+        params.extend(image_info_params(width_param.as_deref()));
+
+        let response: QueryResponse = self.get(&params).await?;
+
+        Ok(response
+            .query
+            .pages
+            .into_iter()
+            .flat_map(|page| page.imageinfo)
+            .map(|info| info.into_file_info(file.to_owned(), width))
+            .next())
+    }
+
+    /// Every file shown on the article `title`, see [`WikiApi::file_info`] for `width`
+    pub async fn page_images(&self, title: &str, width: Option<u32>) -> AppResult<Vec<FileInfo>> {
+        self.generated_files(
+            &[
+                ("titles", title),
+                ("generator", "images"),
+                ("gimlimit", GENERATOR_LIMIT),
+            ],
+            width,
+        )
+        .await
+    }
+
+    /// Files directly in the Commons `category`, see [`WikiApi::file_info`] for `width`
+    pub async fn category_files(
+        &self,
+        category: &str,
+        width: Option<u32>,
+    ) -> AppResult<Vec<FileInfo>> {
+        let title = format!("Category:{category}");
+        self.generated_files(
+            &[
+                ("generator", "categorymembers"),
+                ("gcmtitle", &title),
+                ("gcmtype", "file"),
+                ("gcmlimit", GENERATOR_LIMIT),
+            ],
+            width,
+        )
+        .await
+    }
+
+    /// Image info of the files a `generator` query lists, following `continue` until every
+    /// batch is in
+    async fn generated_files(
+        &self,
+        generator: &[(&str, &str)],
+        width: Option<u32>,
+    ) -> AppResult<Vec<FileInfo>> {
+        let width_param = width.map(|width| width.to_string());
+        let mut params = vec![("action", "query")];
+        params.extend(generator);
+        params.extend(image_info_params(width_param.as_deref()));
+
+        let mut files = Vec::new();
+        let mut next: Vec<(String, String)> = Vec::new();
+        loop {
+            let mut batch = params.clone();
+            batch.extend(
+                next.iter()
+                    .map(|(key, value)| (key.as_str(), value.as_str())),
+            );
+            let response: QueryResponse = self.get(&batch).await?;
+
+            files.extend(response.query.pages.into_iter().flat_map(|page| {
+                let file = FILE_PREFIXES
+                    .iter()
+                    .find_map(|prefix| page.title.strip_prefix(prefix))
+                    .unwrap_or(&page.title)
+                    .to_owned();
+                page.imageinfo
+                    .into_iter()
+                    .map(move |info| info.into_file_info(file.clone(), width))
+            }));
+
+            let Some(rest) = response.next else {
+                return Ok(files);
+            };
+            next = rest
+                .into_iter()
+                .map(|(key, value)| match value {
+                    serde_json::Value::String(value) => (key, value),
+                    value => (key, value.to_string()),
+                })
+                .collect();
+        }
+    }
+
+    /// Id of the Wikidata item with a `property` statement equal to `value`,
+    /// found through the search index since the entity API can't query by value
+    pub async fn find_item(&self, property: &str, value: &str) -> AppResult<Option<String>> {
+        let search = format!("haswbstatement:{property}={value}");
+        let response: QueryResponse = self
+            .get(&[
+                ("action", "query"),
+                ("list", "search"),
+                ("srsearch", &search),
+                ("srlimit", "1"),
+            ])
+            .await?;
+
+        Ok(response
+            .query
+            .search
+            .into_iter()
+            .next()
+            .map(|hit| hit.title))
+    }
+
+    /// Statements and the sitelinks to `sites` of a Wikidata item
+    pub async fn item(&self, id: &str, sites: &[&str]) -> AppResult<Option<WikidataItem>> {
+        let mut response: EntitiesResponse = self
+            .get(&[
+                ("action", "wbgetentities"),
+                ("ids", id),
+                ("props", "claims|sitelinks"),
+                ("sitefilter", &sites.join("|")),
+            ])
+            .await?;
+
+        let Some(entity) = response.entities.remove(id) else {
+            return Ok(None);
+        };
+
+        Ok(Some(WikidataItem {
+            id: id.to_owned(),
+            coat_of_arms: entity.string_claim("P94"),
+            image: entity.string_claim("P18"),
+            commons_category: entity.string_claim("P373"),
+            articles: entity
+                .sitelinks
+                .into_iter()
+                .map(|(site, link)| (site, link.title))
+                .collect(),
+        }))
+    }
+}